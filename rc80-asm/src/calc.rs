@@ -0,0 +1,123 @@
+//! Evaluation of `:calc` expressions.
+//!
+//! Like Octo, expressions have no operator precedence and are evaluated right to left,
+//! so `2 * 3 + 1` is `2 * (3 + 1)`. Parentheses group as usual.
+
+use crate::{parse_number, Token};
+
+/// Resolves names used in an expression to values.
+pub(crate) trait Scope {
+    fn lookup(&self, name: &str) -> Option<f64>;
+}
+
+pub(crate) fn evaluate(tokens: &[Token], scope: &dyn Scope) -> Result<f64, String> {
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        scope,
+    };
+    let value = parser.expression()?;
+    match parser.tokens.get(parser.position) {
+        Some(token) => Err(format!("unexpected '{}' in expression", token.text)),
+        None => Ok(value),
+    }
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    scope: &'a dyn Scope,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Result<&str, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .ok_or_else(|| "unexpected end of expression".to_owned())?;
+        self.position += 1;
+        Ok(&token.text)
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens
+            .get(self.position)
+            .map(|token| token.text.as_str())
+    }
+
+    fn expression(&mut self) -> Result<f64, String> {
+        let left = self.term()?;
+        let Some(op) = self.peek() else {
+            return Ok(left);
+        };
+        // Only shifts can fail, by shifting a negative or too large amount
+        let apply: fn(f64, f64) -> Option<f64> = match op {
+            "+" => |a, b| Some(a + b),
+            "-" => |a, b| Some(a - b),
+            "*" => |a, b| Some(a * b),
+            "/" => |a, b| Some(a / b),
+            "%" => |a, b| Some(a % b),
+            "&" => |a, b| Some((a as i64 & b as i64) as f64),
+            "|" => |a, b| Some((a as i64 | b as i64) as f64),
+            "^" => |a, b| Some((a as i64 ^ b as i64) as f64),
+            "<<" => |a, b| Some((a as i64).checked_shl(shift_amount(b)?)? as f64),
+            ">>" => |a, b| Some((a as i64).checked_shr(shift_amount(b)?)? as f64),
+            "pow" => |a, b| Some(a.powf(b)),
+            "min" => |a, b| Some(a.min(b)),
+            "max" => |a, b| Some(a.max(b)),
+            "<" => |a, b| Some((a < b) as u8 as f64),
+            ">" => |a, b| Some((a > b) as u8 as f64),
+            "<=" => |a, b| Some((a <= b) as u8 as f64),
+            ">=" => |a, b| Some((a >= b) as u8 as f64),
+            "==" => |a, b| Some((a == b) as u8 as f64),
+            "!=" => |a, b| Some((a != b) as u8 as f64),
+            ")" => return Ok(left),
+            other => return Err(format!("unknown operator '{}'", other)),
+        };
+        self.position += 1;
+        let right = self.expression()?;
+        apply(left, right).ok_or_else(|| format!("cannot shift by {}", right))
+    }
+
+    fn term(&mut self) -> Result<f64, String> {
+        let token = self.next()?.to_owned();
+        let unary: fn(f64) -> f64 = match token.as_str() {
+            "(" => {
+                let value = self.expression()?;
+                return match self.next()? {
+                    ")" => Ok(value),
+                    other => Err(format!("expected ')' but found '{}'", other)),
+                };
+            }
+            "-" => |a| -a,
+            "~" => |a| !(a as i64) as f64,
+            "!" => |a| (a == 0.) as u8 as f64,
+            "sin" => f64::sin,
+            "cos" => f64::cos,
+            "tan" => f64::tan,
+            "exp" => f64::exp,
+            "log" => f64::ln,
+            "abs" => f64::abs,
+            "sqrt" => f64::sqrt,
+            "sign" => f64::signum,
+            "ceil" => f64::ceil,
+            "floor" => f64::floor,
+            "PI" => return Ok(std::f64::consts::PI),
+            "E" => return Ok(std::f64::consts::E),
+            _ => {
+                if let Some(value) = parse_number(&token) {
+                    return Ok(value as f64);
+                }
+                return self
+                    .scope
+                    .lookup(&token)
+                    .ok_or_else(|| format!("unknown name '{}' in expression", token));
+            }
+        };
+        Ok(unary(self.term()?))
+    }
+}
+
+fn shift_amount(value: f64) -> Option<u32> {
+    u32::try_from(value as i64).ok()
+}
@@ -0,0 +1,778 @@
+//! An assembler for the Octo CHIP-8 assembly language.
+//!
+//! Supported: the CHIP-8, SUPER-CHIP and XO-CHIP instructions, labels, `:const`, `:alias`,
+//! `:macro`, `:calc`, `:byte`, `:org`, `:call`, `if ... then`,
+//! `if ... begin ... else ... end`, and `loop ... while ... again`.
+//!
+//! If the program defines a `main` label and doesn't start with it, a `jump main` is
+//! placed at 0x200, as Octo does.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+
+use rc80_core::{Instruction, SymbolMap, PROGRAM_START};
+
+mod calc;
+
+/// An assembled ROM and the addresses of its labels.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Program {
+    /// ROM bytes, meant to be loaded at 0x200.
+    pub bytes: Vec<u8>,
+    pub symbols: SymbolMap,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    /// 1-based source line the error was found on.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Assembles Octo source code into a ROM.
+pub fn assemble(source: &str) -> Result<Program, AsmError> {
+    Assembler::new(source).run()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Token {
+    pub(crate) text: String,
+    pub(crate) line: usize,
+}
+
+fn tokenize(source: &str) -> VecDeque<Token> {
+    let mut tokens = VecDeque::new();
+    for (index, line) in source.lines().enumerate() {
+        let code = match line.find('#') {
+            Some(comment) => &line[..comment],
+            None => line,
+        };
+        for text in code.split_whitespace() {
+            tokens.push_back(Token {
+                text: text.to_owned(),
+                line: index + 1,
+            });
+        }
+    }
+    tokens
+}
+
+/// Parses a decimal, `0x` hexadecimal or `0b` binary literal, optionally negative.
+pub(crate) fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix(['v', 'V'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// An open structured control flow block.
+enum Block {
+    /// `if ... begin`, with the jump to patch once `else` or `end` is reached.
+    If {
+        jump: usize,
+    },
+    Else {
+        jump: usize,
+    },
+    /// `loop`, with the jumps out of it from `while` statements.
+    Loop {
+        start: u16,
+        exits: Vec<usize>,
+    },
+}
+
+/// An address operand referring to a label that wasn't defined yet.
+struct Fixup {
+    offset: usize,
+    name: String,
+    line: usize,
+    /// Whether this is the 16-bit operand of `i := long` rather than a 12-bit one.
+    long: bool,
+}
+
+/// A condition as written after `if` or `while`, stored as the instruction that skips
+/// the next one when the condition is false.
+#[derive(Clone, Copy)]
+struct Condition {
+    skip_unless: Instruction,
+    skip_if: Instruction,
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    rom: Vec<u8>,
+    /// Address of the next byte, up to 0x10000 once the last byte of memory is filled.
+    here: usize,
+    line: usize,
+    labels: BTreeMap<String, u16>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    blocks: Vec<Block>,
+    fixups: Vec<Fixup>,
+}
+
+struct Names<'a>(&'a Assembler);
+
+impl calc::Scope for Names<'_> {
+    fn lookup(&self, name: &str) -> Option<f64> {
+        if name == "HERE" {
+            return Some(self.0.here as f64);
+        }
+        self.0
+            .constants
+            .get(name)
+            .copied()
+            .or_else(|| self.0.labels.get(name).map(|address| *address as f64))
+    }
+}
+
+impl Assembler {
+    fn new(source: &str) -> Self {
+        Self {
+            tokens: tokenize(source),
+            rom: vec![],
+            here: PROGRAM_START,
+            line: 1,
+            labels: BTreeMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            blocks: vec![],
+            fixups: vec![],
+        }
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, AsmError> {
+        Err(AsmError {
+            line: self.line,
+            message: message.into(),
+        })
+    }
+
+    fn run(mut self) -> Result<Program, AsmError> {
+        let starts_with_main = matches!(
+            (self.tokens.front(), self.tokens.get(1)),
+            (Some(colon), Some(name)) if colon.text == ":" && name.text == "main"
+        );
+        let defines_main = self
+            .tokens
+            .iter()
+            .zip(self.tokens.iter().skip(1))
+            .any(|(colon, name)| colon.text == ":" && name.text == "main");
+        if defines_main && !starts_with_main {
+            self.emit_address(Instruction::Jump(0), "main".to_owned())?;
+        }
+
+        while let Some(token) = self.tokens.pop_front() {
+            self.line = token.line;
+            self.statement(token)?;
+        }
+        if !self.blocks.is_empty() {
+            return self.error("unterminated 'begin' or 'loop' block");
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            let Some(&address) = self.labels.get(&fixup.name) else {
+                return Err(AsmError {
+                    line: fixup.line,
+                    message: format!("undefined name '{}'", fixup.name),
+                });
+            };
+            if fixup.long {
+                self.patch_long(fixup.offset, address);
+            } else if address > 0xFFF {
+                return Err(AsmError {
+                    line: fixup.line,
+                    message: format!("address {:#x} of '{}' is out of range", address, fixup.name),
+                });
+            } else {
+                self.patch(fixup.offset, address)?;
+            }
+        }
+
+        let mut symbols = SymbolMap::new();
+        for (name, address) in &self.labels {
+            symbols.insert(name.clone(), *address);
+        }
+        Ok(Program {
+            bytes: self.rom,
+            symbols,
+        })
+    }
+
+    fn next(&mut self) -> Result<Token, AsmError> {
+        match self.tokens.pop_front() {
+            Some(token) => {
+                self.line = token.line;
+                Ok(token)
+            }
+            None => self.error("unexpected end of file"),
+        }
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), AsmError> {
+        let token = self.next()?;
+        if token.text != text {
+            return self.error(format!("expected '{}' but found '{}'", text, token.text));
+        }
+        Ok(())
+    }
+
+    /// Collects tokens up to the `}` closing an already consumed `{`.
+    fn braced(&mut self) -> Result<Vec<Token>, AsmError> {
+        let mut depth = 1;
+        let mut body = vec![];
+        loop {
+            let token = self.next()?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Ok(body);
+                    }
+                }
+                _ => {}
+            }
+            body.push(token);
+        }
+    }
+
+    fn statement(&mut self, token: Token) -> Result<(), AsmError> {
+        match token.text.as_str() {
+            ":" => {
+                let name = self.next()?.text;
+                if self.labels.contains_key(&name) {
+                    return self.error(format!("label '{}' is already defined", name));
+                }
+                let address = self.here_address()?;
+                self.labels.insert(name, address);
+            }
+            ":const" => {
+                let name = self.next()?.text;
+                let value = self.value()?;
+                self.constants.insert(name, value as f64);
+            }
+            ":alias" => {
+                let name = self.next()?.text;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+            }
+            ":calc" => {
+                let name = self.next()?.text;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+            }
+            ":byte" => {
+                let value = if self.tokens.front().is_some_and(|t| t.text == "{") {
+                    self.next()?;
+                    self.calc()? as i64
+                } else {
+                    self.value()?
+                };
+                let byte = self.to_byte(value)?;
+                self.emit(&[byte])?;
+            }
+            ":org" => {
+                let address = self.value()?;
+                if !(PROGRAM_START as i64..=u16::MAX as i64).contains(&address) {
+                    return self.error(format!("cannot place code at {:#x}", address));
+                }
+                self.here = address as usize;
+            }
+            ":call" => {
+                let instruction = Instruction::Call(0);
+                self.address_operand(instruction)?;
+            }
+            ":macro" => {
+                let name = self.next()?.text;
+                let mut params = vec![];
+                loop {
+                    let param = self.next()?.text;
+                    if param == "{" {
+                        break;
+                    }
+                    params.push(param);
+                }
+                let body = self.braced()?;
+                self.macros.insert(name, Macro { params, body });
+            }
+            ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                self.next()?;
+                self.next()?;
+            }
+            "clear" => self.instruction(Instruction::ClearScreen)?,
+            "return" | ";" => self.instruction(Instruction::Return)?,
+            "hires" => self.instruction(Instruction::HighRes)?,
+            "lores" => self.instruction(Instruction::LowRes)?,
+            "scroll-right" => self.instruction(Instruction::ScrollRight)?,
+            "scroll-left" => self.instruction(Instruction::ScrollLeft)?,
+            "exit" => self.instruction(Instruction::Exit)?,
+            "scroll-down" | "scroll-up" => {
+                let n = self.value()?;
+                if !(0..=15).contains(&n) {
+                    return self.error(format!("scroll amount {} is out of range", n));
+                }
+                self.instruction(match token.text.as_str() {
+                    "scroll-down" => Instruction::ScrollDown(n as u8),
+                    _ => Instruction::ScrollUp(n as u8),
+                })?;
+            }
+            "plane" => {
+                let n = self.value()?;
+                if !(0..=3).contains(&n) {
+                    return self.error(format!("plane mask {} is out of range", n));
+                }
+                self.instruction(Instruction::SelectPlanes(n as u8))?;
+            }
+            "audio" => self.instruction(Instruction::LoadAudio)?,
+            "saveflags" => {
+                let x = self.register()?;
+                self.instruction(Instruction::StoreFlags { x })?;
+            }
+            "loadflags" => {
+                let x = self.register()?;
+                self.instruction(Instruction::LoadFlags { x })?;
+            }
+            "jump" => self.address_operand(Instruction::Jump(0))?,
+            "jump0" => self.address_operand(Instruction::JumpOffset(0))?,
+            "bcd" => {
+                let x = self.register()?;
+                self.instruction(Instruction::StoreBcd { x })?;
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                let save = token.text == "save";
+                let range = if self.tokens.front().is_some_and(|t| t.text == "-") {
+                    self.next()?;
+                    Some(self.register()?)
+                } else {
+                    None
+                };
+                let instruction = match (save, range) {
+                    (true, Some(y)) => Instruction::StoreRange { x, y },
+                    (false, Some(y)) => Instruction::LoadRange { x, y },
+                    (true, None) => Instruction::StoreRegs { x },
+                    (false, None) => Instruction::LoadRegs { x },
+                };
+                self.instruction(instruction)?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.value()?;
+                if !(0..=15).contains(&n) {
+                    return self.error(format!("sprite height {} is out of range", n));
+                }
+                self.instruction(Instruction::Draw { x, y, n: n as u8 })?;
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.instruction(match token.text.as_str() {
+                    "delay" => Instruction::SetDelay { x },
+                    "buzzer" => Instruction::SetSound { x },
+                    _ => Instruction::SetPitch { x },
+                })?;
+            }
+            "i" => match self.next()?.text.as_str() {
+                ":=" => {
+                    let font = self.tokens.front().map(|t| t.text.clone());
+                    if font.as_deref() == Some("long") {
+                        self.next()?;
+                        self.long_address_operand()?;
+                    } else if let Some("hex" | "bighex") = font.as_deref() {
+                        self.next()?;
+                        let x = self.register()?;
+                        self.instruction(match font.as_deref() {
+                            Some("hex") => Instruction::LoadFont { x },
+                            _ => Instruction::LoadBigFont { x },
+                        })?;
+                    } else {
+                        self.address_operand(Instruction::LoadI(0))?;
+                    }
+                }
+                "+=" => {
+                    let x = self.register()?;
+                    self.instruction(Instruction::AddI { x })?;
+                }
+                other => return self.error(format!("unknown operator '{}' for i", other)),
+            },
+            "if" => {
+                let condition = self.condition()?;
+                match self.next()?.text.as_str() {
+                    "then" => self.instruction(condition.skip_unless)?,
+                    "begin" => {
+                        self.instruction(condition.skip_if)?;
+                        let jump = self.placeholder(Instruction::Jump(0))?;
+                        self.blocks.push(Block::If { jump });
+                    }
+                    other => {
+                        return self
+                            .error(format!("expected 'then' or 'begin' but found '{}'", other))
+                    }
+                }
+            }
+            "else" => match self.blocks.pop() {
+                Some(Block::If { jump }) => {
+                    let end_jump = self.placeholder(Instruction::Jump(0))?;
+                    self.patch(jump, self.here_address()?)?;
+                    self.blocks.push(Block::Else { jump: end_jump });
+                }
+                _ => return self.error("'else' without 'if ... begin'"),
+            },
+            "end" => match self.blocks.pop() {
+                Some(Block::If { jump }) | Some(Block::Else { jump }) => {
+                    self.patch(jump, self.here_address()?)?;
+                }
+                _ => return self.error("'end' without 'if ... begin'"),
+            },
+            "loop" => {
+                let start = self.here_address()?;
+                self.blocks.push(Block::Loop {
+                    start,
+                    exits: vec![],
+                })
+            }
+            "while" => {
+                let condition = self.condition()?;
+                self.instruction(condition.skip_if)?;
+                let exit = self.placeholder(Instruction::Jump(0))?;
+                let innermost_loop = self.blocks.iter_mut().rev().find_map(|block| match block {
+                    Block::Loop { exits, .. } => Some(exits),
+                    _ => None,
+                });
+                match innermost_loop {
+                    Some(exits) => exits.push(exit),
+                    None => return self.error("'while' outside of a loop"),
+                }
+            }
+            "again" => match self.blocks.pop() {
+                Some(Block::Loop { start, exits }) => {
+                    self.instruction(Instruction::Jump(self.jump_target(start)?))?;
+                    for exit in exits {
+                        self.patch(exit, self.here_address()?)?;
+                    }
+                }
+                _ => return self.error("'again' without 'loop'"),
+            },
+            text => {
+                if let Some(x) = self.register_name(text) {
+                    return self.register_statement(x);
+                }
+                if let Some(expansion) = self.expand_macro(text)? {
+                    for token in expansion.into_iter().rev() {
+                        self.tokens.push_front(token);
+                    }
+                    return Ok(());
+                }
+                if let Some(value) = self.number_or_constant(text) {
+                    let byte = self.to_byte(value)?;
+                    return self.emit(&[byte]);
+                }
+                // Anything else is a call to a (possibly later) label
+                self.tokens.push_front(token);
+                self.address_operand(Instruction::Call(0))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles statements starting with a register, such as `v0 += 2`.
+    fn register_statement(&mut self, x: u8) -> Result<(), AsmError> {
+        let op = self.next()?.text;
+        let rhs = self.next()?;
+        let y = self.register_name(&rhs.text);
+        let instruction = match (op.as_str(), y) {
+            (":=", Some(y)) => Instruction::LoadReg { x, y },
+            (":=", None) => match rhs.text.as_str() {
+                "random" => {
+                    let mask = self.value()?;
+                    Instruction::Random {
+                        x,
+                        byte: self.to_byte(mask)?,
+                    }
+                }
+                "delay" => Instruction::LoadDelay { x },
+                "key" => Instruction::WaitKey { x },
+                _ => Instruction::LoadByte {
+                    x,
+                    byte: self.byte_token(&rhs.text)?,
+                },
+            },
+            ("+=", Some(y)) => Instruction::AddReg { x, y },
+            ("+=", None) => Instruction::AddByte {
+                x,
+                byte: self.byte_token(&rhs.text)?,
+            },
+            ("-=", Some(y)) => Instruction::Sub { x, y },
+            ("-=", None) => Instruction::AddByte {
+                x,
+                byte: self.byte_token(&rhs.text)?.wrapping_neg(),
+            },
+            ("=-", Some(y)) => Instruction::SubN { x, y },
+            ("|=", Some(y)) => Instruction::Or { x, y },
+            ("&=", Some(y)) => Instruction::And { x, y },
+            ("^=", Some(y)) => Instruction::Xor { x, y },
+            (">>=", Some(y)) => Instruction::ShiftRight { x, y },
+            ("<<=", Some(y)) => Instruction::ShiftLeft { x, y },
+            _ => return self.error(format!("cannot assemble 'v{:x} {} {}'", x, op, rhs.text)),
+        };
+        self.instruction(instruction)
+    }
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.register()?;
+        let op = self.next()?.text;
+        let (skip_unless, skip_if) = match op.as_str() {
+            "key" => (Instruction::SkipNotKey { x }, Instruction::SkipKey { x }),
+            "-key" => (Instruction::SkipKey { x }, Instruction::SkipNotKey { x }),
+            "==" | "!=" => {
+                let rhs = self.next()?;
+                let (equal, not_equal) = match self.register_name(&rhs.text) {
+                    Some(y) => (
+                        Instruction::SkipEqReg { x, y },
+                        Instruction::SkipNeReg { x, y },
+                    ),
+                    None => {
+                        let byte = self.byte_token(&rhs.text)?;
+                        (
+                            Instruction::SkipEqByte { x, byte },
+                            Instruction::SkipNeByte { x, byte },
+                        )
+                    }
+                };
+                if op == "==" {
+                    (not_equal, equal)
+                } else {
+                    (equal, not_equal)
+                }
+            }
+            other => return self.error(format!("unsupported comparison '{}'", other)),
+        };
+        Ok(Condition {
+            skip_unless,
+            skip_if,
+        })
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<Option<Vec<Token>>, AsmError> {
+        let Some(param_count) = self.macros.get(name).map(|m| m.params.len()) else {
+            return Ok(None);
+        };
+        let mut args = vec![];
+        for _ in 0..param_count {
+            args.push(self.next()?.text);
+        }
+        let line = self.line;
+        let definition = &self.macros[name];
+        let expansion = definition
+            .body
+            .iter()
+            .map(|token| {
+                let text = match definition.params.iter().position(|p| *p == token.text) {
+                    Some(index) => args[index].clone(),
+                    None => token.text.clone(),
+                };
+                // Errors inside the expansion are reported at the invocation
+                Token { text, line }
+            })
+            .collect();
+        Ok(Some(expansion))
+    }
+
+    fn calc(&mut self) -> Result<f64, AsmError> {
+        let tokens = self.braced()?;
+        calc::evaluate(&tokens, &Names(self)).or_else(|message| self.error(message))
+    }
+
+    fn register_name(&self, text: &str) -> Option<u8> {
+        parse_register(text).or_else(|| self.aliases.get(text).copied())
+    }
+
+    fn register(&mut self) -> Result<u8, AsmError> {
+        let token = self.next()?;
+        match self.register_name(&token.text) {
+            Some(register) => Ok(register),
+            None => self.error(format!("expected a register but found '{}'", token.text)),
+        }
+    }
+
+    fn number_or_constant(&self, text: &str) -> Option<i64> {
+        parse_number(text).or_else(|| self.constants.get(text).map(|value| *value as i64))
+    }
+
+    fn value(&mut self) -> Result<i64, AsmError> {
+        let token = self.next()?;
+        match self.number_or_constant(&token.text) {
+            Some(value) => Ok(value),
+            None => self.error(format!("expected a number but found '{}'", token.text)),
+        }
+    }
+
+    fn to_byte(&self, value: i64) -> Result<u8, AsmError> {
+        if !(-128..=255).contains(&value) {
+            return self.error(format!("{} does not fit in a byte", value));
+        }
+        Ok(value as u8)
+    }
+
+    fn byte_token(&self, text: &str) -> Result<u8, AsmError> {
+        match self.number_or_constant(text) {
+            Some(value) => self.to_byte(value),
+            None => self.error(format!("expected a number but found '{}'", text)),
+        }
+    }
+
+    /// Emits `instruction` with its 12-bit address taken from the next token, which can
+    /// be a number, a constant or a label defined anywhere in the program.
+    fn address_operand(&mut self, instruction: Instruction) -> Result<(), AsmError> {
+        let token = self.next()?;
+        let address = match self.number_or_constant(&token.text) {
+            Some(address) => Some(address),
+            None => self.labels.get(&token.text).map(|address| *address as i64),
+        };
+        match address {
+            Some(address) if (0..=0xFFF).contains(&address) => {
+                let offset = self.placeholder(instruction)?;
+                self.patch(offset, address as u16)
+            }
+            Some(address) => self.error(format!("address {:#x} is out of range", address)),
+            None => self.emit_address(instruction, token.text),
+        }
+    }
+
+    /// Emits `i := long` with the 16-bit address taken from the next token.
+    fn long_address_operand(&mut self) -> Result<(), AsmError> {
+        let token = self.next()?;
+        let address = match self.number_or_constant(&token.text) {
+            Some(address) => Some(address),
+            None => self.labels.get(&token.text).map(|address| *address as i64),
+        };
+        match address {
+            Some(address) if (0..=0xFFFF).contains(&address) => {
+                self.instruction(Instruction::LoadLongI(address as u16))
+            }
+            Some(address) => self.error(format!("address {:#x} is out of range", address)),
+            None => {
+                let offset = self.placeholder(Instruction::LoadLongI(0))?;
+                self.fixups.push(Fixup {
+                    offset,
+                    name: token.text,
+                    line: self.line,
+                    long: true,
+                });
+                Ok(())
+            }
+        }
+    }
+
+    fn emit_address(&mut self, instruction: Instruction, name: String) -> Result<(), AsmError> {
+        let offset = self.placeholder(instruction)?;
+        self.fixups.push(Fixup {
+            offset,
+            name,
+            line: self.line,
+            long: false,
+        });
+        Ok(())
+    }
+
+    /// Emits an instruction whose address is filled in later, returning its offset.
+    fn placeholder(&mut self, instruction: Instruction) -> Result<usize, AsmError> {
+        let offset = self.here - PROGRAM_START;
+        self.instruction(instruction)?;
+        Ok(offset)
+    }
+
+    /// Fills in the jump target of the placeholder at `offset`.
+    fn patch(&mut self, offset: usize, address: u16) -> Result<(), AsmError> {
+        let address = self.jump_target(address)?;
+        self.rom[offset] = self.rom[offset] & 0xF0 | (address >> 8) as u8;
+        self.rom[offset + 1] = address as u8;
+        Ok(())
+    }
+
+    /// Checks that a block's jump can reach `address`, which has to fit in 12 bits.
+    fn jump_target(&self, address: u16) -> Result<u16, AsmError> {
+        if address > 0xFFF {
+            return self.error(format!("address {:#x} is out of range", address));
+        }
+        Ok(address)
+    }
+
+    /// The current address, for things that refer to it. Fails once the program has
+    /// filled memory up to the end.
+    fn here_address(&self) -> Result<u16, AsmError> {
+        match u16::try_from(self.here) {
+            Ok(address) => Ok(address),
+            Err(_) => self.error("address is past the end of memory"),
+        }
+    }
+
+    /// Fills in the second word of an `F000 NNNN` instruction at `offset`.
+    fn patch_long(&mut self, offset: usize, address: u16) {
+        self.rom[offset + 2..offset + 4].copy_from_slice(&address.to_be_bytes());
+    }
+
+    fn instruction(&mut self, instruction: Instruction) -> Result<(), AsmError> {
+        match instruction {
+            Instruction::LoadLongI(address) => {
+                let [high, low] = address.to_be_bytes();
+                let [op_high, op_low] = instruction.encode().to_be_bytes();
+                self.emit(&[op_high, op_low, high, low])
+            }
+            _ => self.emit(&instruction.encode().to_be_bytes()),
+        }
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), AsmError> {
+        let offset = self.here - PROGRAM_START;
+        let end = offset + bytes.len();
+        if end + PROGRAM_START > u16::MAX as usize + 1 {
+            return self.error("program does not fit in memory");
+        }
+        if self.rom.len() < end {
+            self.rom.resize(end, 0);
+        }
+        self.rom[offset..end].copy_from_slice(bytes);
+        self.here = end + PROGRAM_START;
+        Ok(())
+    }
+}
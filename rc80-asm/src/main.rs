@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: rc80-asm <source.8o> [-o rom.ch8] [--symbols symbols.sym]";
+
+fn main() -> ExitCode {
+    let mut input = None;
+    let mut output = None;
+    let mut symbols = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = args.next().map(PathBuf::from),
+            "--symbols" => symbols = args.next().map(PathBuf::from),
+            _ if input.is_none() => input = Some(PathBuf::from(arg)),
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let Some(input) = input else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let output = output.unwrap_or_else(|| input.with_extension("ch8"));
+
+    let source = match std::fs::read_to_string(&input) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("cannot read {}: {}", input.display(), err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let program = match rc80_asm::assemble(&source) {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!("{}: {}", input.display(), err);
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(err) = std::fs::write(&output, &program.bytes) {
+        eprintln!("cannot write {}: {}", output.display(), err);
+        return ExitCode::FAILURE;
+    }
+    if let Some(path) = symbols {
+        if let Err(err) = std::fs::write(&path, program.symbols.to_text()) {
+            eprintln!("cannot write {}: {}", path.display(), err);
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
@@ -0,0 +1,221 @@
+//! Assembles small Octo programs and checks the bytes and labels they produce.
+
+use rc80_asm::{assemble, AsmError};
+use rc80_core::disasm::{self, Syntax};
+use rc80_core::{Instruction, Variant, PROGRAM_START};
+
+fn bytes(source: &str) -> Vec<u8> {
+    match assemble(source) {
+        Ok(program) => program.bytes,
+        Err(err) => panic!("{}", err),
+    }
+}
+
+fn error(source: &str) -> AsmError {
+    assemble(source).expect_err("source should not assemble")
+}
+
+#[test]
+fn instructions() {
+    assert_eq!(
+        bytes("clear v3 := 0x10 v3 += v4 i := 0x123 sprite v1 v2 5 return"),
+        [0x00, 0xE0, 0x63, 0x10, 0x83, 0x44, 0xA1, 0x23, 0xD1, 0x25, 0x00, 0xEE]
+    );
+    assert_eq!(
+        bytes("v0 := random 0x0f delay := v1 v2 := key i := hex v3 bcd v4 save v5"),
+        [0xC0, 0x0F, 0xF1, 0x15, 0xF2, 0x0A, 0xF3, 0x29, 0xF4, 0x33, 0xF5, 0x55]
+    );
+    assert_eq!(
+        bytes("hires scroll-down 4 plane 3 i := long 0x1234 save v1 - v3"),
+        [0x00, 0xFF, 0x00, 0xC4, 0xF3, 0x01, 0xF0, 0x00, 0x12, 0x34, 0x51, 0x32]
+    );
+}
+
+#[test]
+fn labels_resolve_backwards_and_forwards() {
+    let program = assemble(
+        ": start
+            jump end
+            start
+            i := sprite
+        : end
+            jump start
+        : sprite
+            0x80",
+    )
+    .unwrap();
+    assert_eq!(
+        program.bytes,
+        [0x12, 0x06, 0x22, 0x00, 0xA2, 0x08, 0x12, 0x00, 0x80]
+    );
+    assert_eq!(program.symbols.get("start"), Some(0x200));
+    assert_eq!(program.symbols.get("end"), Some(0x206));
+    assert_eq!(program.symbols.get("sprite"), Some(0x208));
+}
+
+#[test]
+fn main_gets_a_jump_when_it_is_not_first() {
+    assert_eq!(
+        bytes(": data 0x01 0x02 : main jump main"),
+        [0x12, 0x04, 0x01, 0x02, 0x12, 0x04]
+    );
+}
+
+#[test]
+fn long_loads_resolve_forwards() {
+    assert_eq!(
+        bytes("i := long far :org 0x1000 : far 0xAA"),
+        [
+            [0xF0, 0x00, 0x10, 0x00].as_slice(),
+            &[0; 0x1000 - PROGRAM_START - 4],
+            &[0xAA]
+        ]
+        .concat()
+    );
+}
+
+#[test]
+fn constants_aliases_and_calc() {
+    assert_eq!(
+        bytes(
+            ":const SPEED 3
+            :alias x v4
+            :calc DOUBLE { SPEED * 2 }
+            :calc OFFSET { 2 * 3 + 1 }
+            x := SPEED
+            x += DOUBLE
+            :byte OFFSET
+            :byte { HERE - 0x200 }"
+        ),
+        [0x64, 0x03, 0x74, 0x06, 0x08, 0x05]
+    );
+}
+
+#[test]
+fn calc_shifts_stay_in_range() {
+    assert_eq!(
+        bytes(":calc X { 1 << 4 } :byte X :byte { 256 >> 8 }"),
+        [16, 1]
+    );
+    for shift in [
+        "1 << 64",
+        "1 << -1",
+        "1 >> 64",
+        "1 >> -1",
+        "1 << ( 2 pow 40 )",
+    ] {
+        let err = error(&format!("clear\n:calc X {{ {} }}", shift));
+        assert_eq!(err.line, 2, "{}", shift);
+        assert!(err.message.contains("shift"), "{}", err.message);
+    }
+}
+
+#[test]
+fn macros_substitute_their_arguments() {
+    assert_eq!(
+        bytes(
+            ":macro set-both reg value { reg := value v0 := value }
+            set-both v1 7
+            set-both v2 0x20"
+        ),
+        [0x61, 0x07, 0x60, 0x07, 0x62, 0x20, 0x60, 0x20]
+    );
+}
+
+#[test]
+fn if_then() {
+    // `then` skips the next instruction unless the condition holds
+    assert_eq!(
+        bytes("if v0 == 1 then v1 := 2 if v0 != v3 then v1 := 3 if v2 key then clear"),
+        [0x40, 0x01, 0x61, 0x02, 0x50, 0x30, 0x61, 0x03, 0xE2, 0xA1, 0x00, 0xE0]
+    );
+}
+
+#[test]
+fn if_begin_else_end() {
+    assert_eq!(
+        bytes("if v0 == 1 begin v1 := 2 else v1 := 3 end clear"),
+        [0x30, 0x01, 0x12, 0x08, 0x61, 0x02, 0x12, 0x0A, 0x61, 0x03, 0x00, 0xE0]
+    );
+    assert_eq!(
+        bytes("if v0 == 1 begin v1 := 2 end"),
+        [0x30, 0x01, 0x12, 0x06, 0x61, 0x02]
+    );
+}
+
+#[test]
+fn loop_while_again() {
+    assert_eq!(
+        bytes("loop v0 += 1 while v0 != 5 again clear"),
+        [0x70, 0x01, 0x40, 0x05, 0x12, 0x08, 0x12, 0x00, 0x00, 0xE0]
+    );
+    // `while` exits the innermost loop
+    assert_eq!(
+        bytes("loop loop while v0 == 1 again while v1 == 2 again"),
+        [0x30, 0x01, 0x12, 0x06, 0x12, 0x00, 0x31, 0x02, 0x12, 0x0C, 0x12, 0x00]
+    );
+}
+
+#[test]
+fn errors_report_their_line() {
+    let err = error("clear\nv0 := 1\nv0 := bogus");
+    assert_eq!(err.line, 3);
+    assert_eq!(err.to_string(), format!("line 3: {}", err.message));
+
+    assert_eq!(error("clear\njump nowhere\nclear").line, 2);
+    assert_eq!(error(": a\nclear\n: a").line, 3);
+    assert_eq!(error("\n\nsprite v0 v1 16").line, 3);
+    assert_eq!(error("loop\nclear").line, 2);
+    assert_eq!(error("clear\nelse").line, 2);
+    assert_eq!(error("v0 :=").line, 1);
+}
+
+#[test]
+fn blocks_past_0xfff_are_rejected() {
+    // The loop starts past the reach of a jump
+    assert_eq!(error(":org 0x1000\nloop\nclear\nagain").line, 4);
+    // The jumps out of these blocks land past it
+    assert_eq!(error(":org 0xFFC\nif v0 == 1 begin\nclear\nend").line, 4);
+    assert_eq!(
+        error(":org 0xFFA\nif v0 == 1 begin\nclear\nelse\nclear\nend").line,
+        4
+    );
+    assert_eq!(error(":org 0xFFA\nloop\nwhile v0 == 1\nagain").line, 4);
+    // Blocks whose jumps land just before it are fine
+    assert_eq!(
+        bytes(":org 0xFF8\nif v0 == 1 begin\nclear\nend")[0xFF8 - PROGRAM_START..],
+        [0x30, 0x01, 0x1F, 0xFE, 0x00, 0xE0]
+    );
+}
+
+#[test]
+fn programs_may_fill_memory_to_the_end() {
+    let source = format!(":org {:#x} clear", 0xFFFE);
+    let program = bytes(&source);
+    assert_eq!(program.len(), 0x10000 - PROGRAM_START);
+    assert_eq!(program[program.len() - 2..], [0x00, 0xE0]);
+
+    let source = format!(":org {:#x} clear clear", 0xFFFE);
+    assert_eq!(error(&source).line, 1);
+    let source = format!(":org {:#x} clear : past", 0xFFFE);
+    assert_eq!(error(&source).line, 1);
+}
+
+/// Every instruction disassembled to Octo assembles back to the same bytes.
+#[test]
+fn round_trips_through_the_disassembler() {
+    // A sample spread over the whole opcode space, then targets that get labels
+    let mut rom: Vec<u8> = (0..=0xFFFF)
+        .step_by(97)
+        .filter_map(Instruction::decode)
+        .flat_map(|instruction| instruction.encode().to_be_bytes())
+        .collect();
+    rom.extend([0x22, 0x00, 0x12, 0x02, 0xA2, 0x04, 0xF0, 0x00, 0x02, 0x00]);
+    // Trailing odd byte of data
+    rom.push(0x42);
+
+    let listing =
+        disasm::disassemble(&rom, PROGRAM_START as u16, Variant::XoChip).render(Syntax::Octo);
+    let program = assemble(&listing).unwrap_or_else(|err| panic!("{}\n{}", err, listing));
+    assert_eq!(program.bytes, rom);
+}
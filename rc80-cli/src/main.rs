@@ -0,0 +1,300 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::process::ExitCode;
+
+use rc80_core::{EmuError, System, Variant, KEY_AMOUNT, TIMER_FREQUENCY};
+
+mod screen;
+#[cfg(test)]
+mod tests;
+
+const USAGE: &str = "usage: rc80-cli [options] <rom.ch8>
+
+options:
+  --variant chip8|superchip|xochip  platform, picked from the extension by default
+  --seed N                          seed of the CXNN random numbers, 0 by default
+  --ipf N                           instructions per frame
+  --frames N                        frames to run at most, 10 seconds worth by default
+  --until-pc ADDR                   stop once the program counter reaches ADDR
+  --until-halt                      stop once the program exits with 00FD
+  --cycles N                        stop after N instructions
+  --press FRAME:KEY[:FRAMES]        hold hex KEY from FRAME on, for 1 frame by default
+  --screen FILE                     write the final screen as PNG or PBM, picked from
+                                    the extension, or as ASCII; - prints it
+
+Exits with 0 once a stop condition is met, or after --frames if none was given, 1 on
+bad arguments or I/O errors, 2 on emulator errors and 3 if --frames ran out first.";
+
+/// Bad arguments or I/O errors.
+const EXIT_FAILURE: u8 = 1;
+/// Emulator error while running.
+const EXIT_EMU_ERROR: u8 = 2;
+/// The frame limit was reached before any of the requested stop conditions.
+const EXIT_TIMEOUT: u8 = 3;
+
+/// A scripted key press.
+struct Press {
+    frame: u64,
+    key: u8,
+    frames: u64,
+}
+
+struct Options {
+    path: String,
+    variant: Option<Variant>,
+    seed: u64,
+    instructions_per_frame: Option<u32>,
+    frames: u64,
+    until_pc: Option<u16>,
+    until_halt: bool,
+    cycles: Option<u64>,
+    presses: Vec<Press>,
+    screen: Option<String>,
+}
+
+impl Options {
+    fn has_stop_condition(&self) -> bool {
+        self.until_pc.is_some() || self.until_halt || self.cycles.is_some()
+    }
+}
+
+/// Why the run ended.
+enum Stop {
+    Frames,
+    Pc,
+    Halted,
+    Cycles,
+    Error(EmuError),
+}
+
+struct Outcome {
+    stop: Stop,
+    frames: u64,
+    cycles: u64,
+}
+
+/// Parses a number in decimal or, with a `0x` prefix, in hex.
+fn parse_number<T: TryFrom<u64>>(text: &str) -> Option<T> {
+    let value = match text.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok()?,
+        None => text.parse().ok()?,
+    };
+    T::try_from(value).ok()
+}
+
+fn parse_press(text: &str) -> Option<Press> {
+    let mut parts = text.split(':');
+    let frame = parse_number(parts.next()?)?;
+    let key = u8::from_str_radix(parts.next()?, 16)
+        .ok()
+        .filter(|key| (*key as usize) < KEY_AMOUNT)?;
+    let frames = match parts.next() {
+        Some(frames) => parse_number(frames)?,
+        None => 1,
+    };
+    parts
+        .next()
+        .is_none()
+        .then_some(Press { frame, key, frames })
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Option<Options> {
+    let mut options = Options {
+        path: String::new(),
+        variant: None,
+        seed: 0,
+        instructions_per_frame: None,
+        frames: 10 * TIMER_FREQUENCY as u64,
+        until_pc: None,
+        until_halt: false,
+        cycles: None,
+        presses: vec![],
+        screen: None,
+    };
+    let mut path = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--variant" => {
+                options.variant = Some(match args.next()?.as_str() {
+                    "chip8" => Variant::Chip8,
+                    "superchip" => Variant::SuperChip,
+                    "xochip" => Variant::XoChip,
+                    _ => return None,
+                })
+            }
+            "--seed" => options.seed = parse_number(&args.next()?)?,
+            "--ipf" => options.instructions_per_frame = Some(parse_number(&args.next()?)?),
+            "--frames" => options.frames = parse_number(&args.next()?)?,
+            "--until-pc" => options.until_pc = Some(parse_number(&args.next()?)?),
+            "--until-halt" => options.until_halt = true,
+            "--cycles" => options.cycles = Some(parse_number(&args.next()?)?),
+            "--press" => options.presses.push(parse_press(&args.next()?)?),
+            "--screen" => options.screen = Some(args.next()?),
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => return None,
+        }
+    }
+    options.path = path?;
+    Some(options)
+}
+
+/// Returns the condition that should stop the run before the next instruction, if any.
+fn check_stop(sys: &System, options: &Options, cycles: u64) -> Option<Stop> {
+    if options.until_pc == Some(sys.program_counter) {
+        Some(Stop::Pc)
+    } else if options.until_halt && sys.is_halted() {
+        Some(Stop::Halted)
+    } else if options.cycles.is_some_and(|limit| cycles >= limit) {
+        Some(Stop::Cycles)
+    } else {
+        None
+    }
+}
+
+/// Runs frame by frame like `System::run_frame`, but checks the stop conditions before
+/// every instruction.
+fn run(sys: &mut System, options: &Options) -> Outcome {
+    let mut cycles = 0;
+    for frame in 0..options.frames {
+        let keys = options
+            .presses
+            .iter()
+            .filter(|press| {
+                (press.frame..press.frame.saturating_add(press.frames)).contains(&frame)
+            })
+            .fold(0, |keys, press| keys | 1 << press.key);
+        sys.set_pressed_keys(keys);
+        for _ in 0..sys.instructions_per_frame {
+            let stop =
+                check_stop(sys, options, cycles).or_else(|| sys.step().err().map(Stop::Error));
+            if let Some(stop) = stop {
+                return Outcome {
+                    stop,
+                    frames: frame,
+                    cycles,
+                };
+            }
+            cycles += 1;
+        }
+        sys.tick_timers();
+    }
+    Outcome {
+        stop: check_stop(sys, options, cycles).unwrap_or(Stop::Frames),
+        frames: options.frames,
+        cycles,
+    }
+}
+
+fn write_registers(sys: &System, mut out: impl Write) -> io::Result<()> {
+    writeln!(
+        out,
+        "PC {:#06x}  I {:#06x}  SP {}  DT {:#04x}  ST {:#04x}",
+        sys.program_counter,
+        sys.i_register,
+        sys.stack_pointer,
+        sys.time_register,
+        sys.sound_register
+    )?;
+    let registers: Vec<_> = sys
+        .v_registers
+        .iter()
+        .enumerate()
+        .map(|(index, value)| format!("V{:X} {:#04x}", index, value))
+        .collect();
+    writeln!(out, "{}", registers[..8].join("  "))?;
+    writeln!(out, "{}", registers[8..].join("  "))?;
+    let stack: Vec<_> = sys.stack[..sys.stack_pointer as usize]
+        .iter()
+        .map(|address| format!("{:#06x}", address))
+        .collect();
+    writeln!(out, "stack [{}]", stack.join(" "))
+}
+
+fn write_screen(sys: &System, path: &str, out: impl Write) -> io::Result<()> {
+    if path == "-" {
+        return screen::write(sys.display(), screen::Format::Ascii, out);
+    }
+    // Dropping a BufWriter would swallow the error of its last write
+    let mut file = BufWriter::new(File::create(path)?);
+    screen::write(sys.display(), screen::Format::from_path(path), &mut file)?;
+    file.flush()
+}
+
+/// Runs the command line `args` and returns the exit code. Results go to `out`,
+/// errors to stderr.
+fn cli(args: Vec<String>, mut out: impl Write) -> io::Result<u8> {
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        writeln!(out, "{}", USAGE)?;
+        return Ok(0);
+    }
+    let Some(options) = parse_args(args) else {
+        eprintln!("{}", USAGE);
+        return Ok(EXIT_FAILURE);
+    };
+    let bytes = match std::fs::read(&options.path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("cannot read {}: {}", options.path, err);
+            return Ok(EXIT_FAILURE);
+        }
+    };
+
+    let mut sys = System::with_seed(options.seed);
+    let variant = options.variant.unwrap_or_else(|| {
+        // Same conventional extensions as the desktop app
+        if options.path.ends_with(".sc8") {
+            Variant::SuperChip
+        } else if options.path.ends_with(".xo8") {
+            Variant::XoChip
+        } else {
+            Variant::Chip8
+        }
+    });
+    sys.set_variant(variant);
+    if let Some(instructions_per_frame) = options.instructions_per_frame {
+        sys.instructions_per_frame = instructions_per_frame;
+    }
+    if let Err(err) = sys.load(&bytes) {
+        eprintln!("{}: {}", options.path, err);
+        return Ok(EXIT_EMU_ERROR);
+    }
+
+    let outcome = run(&mut sys, &options);
+    let (reason, code) = match outcome.stop {
+        Stop::Frames if options.has_stop_condition() => ("frame limit".to_owned(), EXIT_TIMEOUT),
+        Stop::Frames => ("frame limit".to_owned(), 0),
+        Stop::Pc => ("program counter reached".to_owned(), 0),
+        Stop::Halted => ("halted".to_owned(), 0),
+        Stop::Cycles => ("cycle limit".to_owned(), 0),
+        Stop::Error(err) => {
+            eprintln!("{}: {}", options.path, err);
+            (err.to_string(), EXIT_EMU_ERROR)
+        }
+    };
+
+    if let Some(path) = &options.screen {
+        if let Err(err) = write_screen(&sys, path, &mut out) {
+            eprintln!("cannot write {}: {}", path, err);
+            return Ok(EXIT_FAILURE);
+        }
+    }
+    writeln!(
+        out,
+        "stopped after {} frames and {} instructions: {}",
+        outcome.frames, outcome.cycles, reason
+    )?;
+    write_registers(&sys, &mut out)?;
+    Ok(code)
+}
+
+fn main() -> ExitCode {
+    let mut out = BufWriter::new(io::stdout().lock());
+    let code = cli(std::env::args().skip(1).collect(), &mut out)
+        .and_then(|code| out.flush().map(|()| code))
+        .unwrap_or_else(|err| {
+            eprintln!("cannot write the output: {}", err);
+            EXIT_FAILURE
+        });
+    ExitCode::from(code)
+}
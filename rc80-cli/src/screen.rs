@@ -0,0 +1,80 @@
+use std::io::{self, Write};
+
+use rc80_core::Display;
+
+/// Pixel colors indexed by the planes a pixel is lit on, same as the desktop app.
+const PALETTE: [[u8; 3]; 4] = [[0, 0, 0], [255, 255, 255], [255, 102, 0], [102, 33, 0]];
+/// Characters for the ASCII dump, indexed the same way.
+const ASCII: [char; 4] = ['.', '#', 'o', '@'];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Pbm,
+    Ascii,
+}
+
+impl Format {
+    /// Picks the format from a file name, ASCII unless it ends in `.png` or `.pbm`.
+    pub fn from_path(path: &str) -> Self {
+        if path.ends_with(".png") {
+            Format::Png
+        } else if path.ends_with(".pbm") {
+            Format::Pbm
+        } else {
+            Format::Ascii
+        }
+    }
+}
+
+pub fn write(display: &Display, format: Format, out: impl Write) -> io::Result<()> {
+    match format {
+        Format::Png => write_png(display, out),
+        Format::Pbm => write_pbm(display, out),
+        Format::Ascii => write_ascii(display, out),
+    }
+}
+
+/// An RGB image in the desktop palette, one image pixel per display pixel.
+fn write_png(display: &Display, out: impl Write) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(out, display.width() as u32, display.height() as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let data: Vec<u8> = display
+        .to_pixels()
+        .into_iter()
+        .flat_map(|planes| PALETTE[planes as usize & 3])
+        .collect();
+    encoder
+        .write_header()
+        .and_then(|mut writer| {
+            writer.write_image_data(&data)?;
+            writer.finish()
+        })
+        .map_err(io::Error::other)
+}
+
+/// A plain PBM bitmap, where a pixel lit on any plane is black.
+fn write_pbm(display: &Display, mut out: impl Write) -> io::Result<()> {
+    writeln!(out, "P1")?;
+    writeln!(out, "{} {}", display.width(), display.height())?;
+    for y in 0..display.height() {
+        let row: Vec<_> = (0..display.width())
+            .map(|x| if display.get(x, y) != 0 { "1" } else { "0" })
+            .collect();
+        writeln!(out, "{}", row.join(" "))?;
+    }
+    Ok(())
+}
+
+/// One character per pixel, `.` for unlit, `#` for the first plane, `o` for the second
+/// and `@` for both.
+fn write_ascii(display: &Display, mut out: impl Write) -> io::Result<()> {
+    for y in 0..display.height() {
+        let row: String = (0..display.width())
+            .map(|x| ASCII[display.get(x, y) as usize & 3])
+            .collect();
+        writeln!(out, "{}", row)?;
+    }
+    Ok(())
+}
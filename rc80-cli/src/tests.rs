@@ -0,0 +1,220 @@
+//! Unit tests for the argument parser and whole runs of small ROMs.
+
+use super::*;
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+/// Writes `program`, one opcode per word, to a ROM named after the test, runs the
+/// command line `options` on it and returns the exit code and output.
+fn run_rom(name: &str, program: &[u16], options: &[&str]) -> (u8, String) {
+    let path = std::env::temp_dir().join(format!("rc80-cli-{}-{}.ch8", std::process::id(), name));
+    let bytes: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+    std::fs::write(&path, bytes).unwrap();
+    let mut out = vec![];
+    let mut command_line = args(options);
+    command_line.push(path.to_str().unwrap().to_owned());
+    let code = cli(command_line, &mut out).unwrap();
+    std::fs::remove_file(path).unwrap();
+    (code, String::from_utf8(out).unwrap())
+}
+
+// Arguments
+
+#[test]
+fn parses_presses() {
+    let press = parse_press("3:a").unwrap();
+    assert_eq!((press.frame, press.key, press.frames), (3, 10, 1));
+    let press = parse_press("0x10:F:5").unwrap();
+    assert_eq!((press.frame, press.key, press.frames), (16, 15, 5));
+    for bad in ["", "1", "1:10", "1:g", "x:1", "1:2:3:4", "-1:2"] {
+        assert!(parse_press(bad).is_none(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn parses_options() {
+    let options = parse_args(args(&[
+        "--variant",
+        "xochip",
+        "--seed",
+        "0x2A",
+        "--ipf",
+        "100",
+        "--frames",
+        "5",
+        "--until-pc",
+        "0x300",
+        "--until-halt",
+        "--cycles",
+        "7",
+        "--press",
+        "2:1:3",
+        "--press",
+        "4:f",
+        "--screen",
+        "out.png",
+        "rom.ch8",
+    ]))
+    .unwrap();
+    assert_eq!(options.path, "rom.ch8");
+    assert_eq!(options.variant, Some(Variant::XoChip));
+    assert_eq!(options.seed, 42);
+    assert_eq!(options.instructions_per_frame, Some(100));
+    assert_eq!(options.frames, 5);
+    assert_eq!(options.until_pc, Some(0x300));
+    assert!(options.until_halt);
+    assert_eq!(options.cycles, Some(7));
+    assert_eq!(options.presses.len(), 2);
+    assert_eq!(options.screen.as_deref(), Some("out.png"));
+
+    let options = parse_args(args(&["rom.ch8"])).unwrap();
+    assert_eq!(options.variant, None);
+    assert_eq!(options.frames, 10 * TIMER_FREQUENCY as u64);
+    assert!(!options.has_stop_condition());
+}
+
+#[test]
+fn rejects_bad_arguments() {
+    for bad in [
+        &[][..],
+        &["--frames", "5"],
+        &["a.ch8", "b.ch8"],
+        &["--variant", "chip48", "rom.ch8"],
+        &["--until-pc", "0x10000", "rom.ch8"],
+        &["--press", "1:10", "rom.ch8"],
+        &["--fast", "rom.ch8"],
+        &["rom.ch8", "--seed"],
+    ] {
+        assert!(parse_args(args(bad)).is_none(), "{:?}", bad);
+    }
+}
+
+// Runs
+
+#[test]
+fn stops_at_the_program_counter_and_dumps_the_registers() {
+    let (code, out) = run_rom(
+        "until-pc",
+        &[0x6001, 0x6AFF, 0xA123, 0x220A, 0x1208, 0x120A],
+        &["--until-pc", "0x20A"],
+    );
+    assert_eq!(code, 0);
+    assert_eq!(
+        out,
+        "stopped after 0 frames and 4 instructions: program counter reached\n\
+         PC 0x020a  I 0x0123  SP 1  DT 0x00  ST 0x00\n\
+         V0 0x01  V1 0x00  V2 0x00  V3 0x00  V4 0x00  V5 0x00  V6 0x00  V7 0x00\n\
+         V8 0x00  V9 0x00  VA 0xff  VB 0x00  VC 0x00  VD 0x00  VE 0x00  VF 0x00\n\
+         stack [0x0206]\n"
+    );
+}
+
+#[test]
+fn stops_when_halted() {
+    let (code, out) = run_rom(
+        "until-halt",
+        &[0x6001, 0x00FD],
+        &["--variant", "superchip", "--until-halt"],
+    );
+    assert_eq!(code, 0);
+    assert!(out.starts_with("stopped after 0 frames and 2 instructions: halted\n"));
+}
+
+#[test]
+fn holds_pressed_keys() {
+    // Waits for key 5, which is held from frame 2 on
+    let program = [0x6005, 0xE09E, 0x1202, 0x1206];
+    let (code, out) = run_rom(
+        "press",
+        &program,
+        &["--until-pc", "0x206", "--press", "2:5"],
+    );
+    assert_eq!(code, 0);
+    assert!(out.starts_with("stopped after 2 frames"), "{}", out);
+    // Never held if the frames run out first
+    let (code, out) = run_rom(
+        "press-early",
+        &program,
+        &["--until-pc", "0x206", "--press", "2:5", "--frames", "1"],
+    );
+    assert_eq!(code, EXIT_TIMEOUT);
+    assert!(out.contains(": frame limit\n"));
+}
+
+#[test]
+fn runs_out_the_frames() {
+    // Without a stop condition, running out of frames is the expected end
+    let (code, out) = run_rom("frames", &[0x1200], &["--frames", "2", "--ipf", "3"]);
+    assert_eq!(code, 0);
+    assert!(out.starts_with("stopped after 2 frames and 6 instructions: frame limit\n"));
+    let (code, _) = run_rom(
+        "timeout",
+        &[0x1200],
+        &["--frames", "2", "--ipf", "3", "--cycles", "7"],
+    );
+    assert_eq!(code, EXIT_TIMEOUT);
+    let (code, out) = run_rom(
+        "cycles",
+        &[0x1200],
+        &["--frames", "2", "--ipf", "3", "--cycles", "5"],
+    );
+    assert_eq!(code, 0);
+    assert!(out.contains(": cycle limit\n"));
+}
+
+#[test]
+fn reports_emulator_errors() {
+    let (code, out) = run_rom("underflow", &[0x6001, 0x00EE], &[]);
+    assert_eq!(code, EXIT_EMU_ERROR);
+    assert!(out.starts_with("stopped after 0 frames and 1 instructions: stack underflow"));
+    let (code, out) = run_rom("too-large", &[0x1200; 0x800], &[]);
+    assert_eq!(code, EXIT_EMU_ERROR);
+    assert_eq!(out, "");
+}
+
+#[test]
+fn reports_bad_arguments_and_io_errors() {
+    let (code, out) = run_rom("unknown", &[0x1200], &["--fast"]);
+    assert_eq!((code, out.as_str()), (EXIT_FAILURE, ""));
+    let missing = std::env::temp_dir()
+        .join("rc80-cli-missing")
+        .join("rom.ch8");
+    let mut out = vec![];
+    assert_eq!(
+        cli(args(&[missing.to_str().unwrap()]), &mut out).unwrap(),
+        EXIT_FAILURE
+    );
+    let screen = std::env::temp_dir()
+        .join("rc80-cli-missing")
+        .join("screen.png");
+    let (code, _) = run_rom(
+        "screen",
+        &[0x1200],
+        &["--frames", "1", "--screen", screen.to_str().unwrap()],
+    );
+    assert_eq!(code, EXIT_FAILURE);
+}
+
+#[test]
+fn prints_the_screen() {
+    // Draws the top of the font's 0 in the corner
+    let (code, out) = run_rom(
+        "screen-ascii",
+        &[0xF029, 0xD001, 0x1204],
+        &["--frames", "1", "--screen", "-"],
+    );
+    assert_eq!(code, 0);
+    let rows: Vec<_> = out.lines().collect();
+    assert_eq!(rows.len(), 32 + 5);
+    assert_eq!(rows[0], format!("####{}", ".".repeat(60)));
+    assert_eq!(rows[1], ".".repeat(64));
+}
+
+#[test]
+fn help_goes_to_the_output() {
+    let mut out = vec![];
+    assert_eq!(cli(args(&["rom.ch8", "--help"]), &mut out).unwrap(), 0);
+    assert_eq!(String::from_utf8(out).unwrap(), format!("{}\n", USAGE));
+}
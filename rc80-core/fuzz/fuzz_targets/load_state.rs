@@ -0,0 +1,26 @@
+//! Feeds arbitrary bytes to `System::load_state`, which reads files from disk.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rc80_core::System;
+
+fuzz_target!(|data: &[u8]| {
+    let mut sys = System::default();
+    let before = sys.save_state();
+    match sys.load_state(data) {
+        Ok(()) => {
+            // Whatever loads must save back the same and run without panicking
+            let saved = sys.save_state();
+            let mut copy = System::default();
+            copy.load_state(&saved).unwrap();
+            assert_eq!(copy.save_state(), saved);
+            for _ in 0..1000 {
+                if sys.step().is_err() {
+                    break;
+                }
+            }
+        }
+        Err(_) => assert_eq!(sys.save_state(), before, "failed load changed the system"),
+    }
+});
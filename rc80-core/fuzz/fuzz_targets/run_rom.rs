@@ -0,0 +1,87 @@
+//! Loads arbitrary bytes as a ROM and runs them, checking that the machine stays in a
+//! valid state. The first two bytes pick the variant and the quirks.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rc80_core::display::MAX_DISPLAY_WIDTH;
+use rc80_core::{EmuError, KeyWaitTrigger, System, Variant, PLANE_AMOUNT, STACK_SIZE};
+
+/// Instructions to run per input, enough to get through a few loops.
+const MAX_STEPS: usize = 10_000;
+
+fn check_invariants(sys: &System) {
+    assert!(
+        (sys.program_counter as usize) < sys.memory.len(),
+        "program counter {:#x} out of memory",
+        sys.program_counter
+    );
+    assert!(
+        sys.stack_pointer as usize <= STACK_SIZE,
+        "stack pointer out of range"
+    );
+    assert!(
+        sys.selected_planes() < 1 << PLANE_AMOUNT,
+        "invalid plane selection"
+    );
+    let display = sys.display();
+    let unused_bits = (MAX_DISPLAY_WIDTH - display.width()) as u32;
+    for plane in 0..display.plane_count() {
+        for y in 0..display.height() {
+            assert!(
+                display.row(plane, y).trailing_zeros() >= unused_bits,
+                "pixels lit past the right edge"
+            );
+        }
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    let [variant, quirks, rom @ ..] = data else {
+        return;
+    };
+    let mut sys = System::with_variant(match variant % 3 {
+        0 => Variant::Chip8,
+        1 => Variant::SuperChip,
+        _ => Variant::XoChip,
+    });
+    let quirk = |bit: u8| quirks >> bit & 1 == 1;
+    sys.quirks.shift_uses_vy = quirk(0);
+    sys.quirks.memory_increments_i = quirk(1);
+    sys.quirks.jump_uses_vx = quirk(2);
+    sys.quirks.logic_resets_vf = quirk(3);
+    sys.quirks.display_wait = quirk(4);
+    sys.quirks.clip_sprites = quirk(5);
+    sys.quirks.collision_counts_rows = quirk(6);
+    if quirk(7) {
+        sys.key_wait_trigger = KeyWaitTrigger::Release;
+    }
+    if sys.load(rom).is_err() {
+        return;
+    }
+
+    let mut steps = 0;
+    for frame in 0u16.. {
+        // Mash the keypad so key waits and skips go both ways
+        sys.set_pressed_keys(frame.wrapping_mul(0x9E37));
+        for _ in 0..sys.instructions_per_frame {
+            if steps == MAX_STEPS {
+                return;
+            }
+            if let Err(err) = sys.step() {
+                // Running into the end of memory is an error, not a panic
+                if sys.program_counter as usize + 2 > sys.memory.len() {
+                    assert!(
+                        matches!(err, EmuError::OutOfBounds { .. }),
+                        "unexpected {} at the end of memory",
+                        err
+                    );
+                }
+                return;
+            }
+            check_invariants(&sys);
+            steps += 1;
+        }
+        sys.tick_timers();
+    }
+});
@@ -0,0 +1,371 @@
+//! Breakpoints and run control on top of `System`.
+//!
+//! A breakpoint triggers on a program counter address, on any instruction matching an
+//! opcode pattern such as `DXYN`, or on every instruction, optionally only when an
+//! [`Expr`] condition holds. The debugger checks them before each instruction and
+//! reports why it stopped.
+
+use std::fmt;
+
+use crate::error::{BreakpointError, EmuError};
+use crate::expr::Expr;
+use crate::System;
+
+/// An opcode with some nibbles left free.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OpcodePattern {
+    mask: u16,
+    value: u16,
+}
+
+impl OpcodePattern {
+    /// Parses four characters, one per nibble. Hex digits must match, anything else
+    /// matches any nibble, so `DXYN` is any sprite draw and `8XY4` any addition.
+    pub fn parse(text: &str) -> Option<Self> {
+        if text.chars().count() != 4 {
+            return None;
+        }
+        let mut pattern = Self { mask: 0, value: 0 };
+        for c in text.chars() {
+            pattern.mask <<= 4;
+            pattern.value <<= 4;
+            if let Some(digit) = c.to_digit(16) {
+                pattern.mask |= 0xF;
+                pattern.value |= digit as u16;
+            }
+        }
+        Some(pattern)
+    }
+
+    pub fn matches(&self, opcode: u16) -> bool {
+        opcode & self.mask == self.value
+    }
+}
+
+/// Prints the fixed nibbles in hex and the free ones as `?`.
+impl fmt::Display for OpcodePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for shift in [12, 8, 4, 0] {
+            if self.mask >> shift & 0xF == 0 {
+                write!(f, "?")?;
+            } else {
+                write!(f, "{:X}", self.value >> shift & 0xF)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Where a breakpoint triggers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Location {
+    /// When the program counter reaches the address.
+    Address(u16),
+    /// When the instruction at the program counter matches the pattern.
+    Opcode(OpcodePattern),
+    /// Before every instruction, which only makes sense with a condition.
+    Anywhere,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub location: Location,
+    /// Only trigger when this evaluates to anything but 0.
+    pub condition: Option<Expr>,
+    pub enabled: bool,
+}
+
+impl Breakpoint {
+    fn new(location: Location) -> Self {
+        Self {
+            location,
+            condition: None,
+            enabled: true,
+        }
+    }
+
+    /// Triggers when the program counter reaches `address`.
+    pub fn at(address: u16) -> Self {
+        Self::new(Location::Address(address))
+    }
+
+    /// Triggers on instructions matching `pattern`.
+    pub fn on_opcode(pattern: OpcodePattern) -> Self {
+        Self::new(Location::Opcode(pattern))
+    }
+
+    /// Triggers before any instruction once `condition` holds.
+    pub fn when(condition: Expr) -> Self {
+        Self::new(Location::Anywhere).with_condition(condition)
+    }
+
+    pub fn with_condition(mut self, condition: Expr) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+
+    /// Parses `LOCATION`, `LOCATION if CONDITION` or `if CONDITION`, where the location
+    /// is an address like `0x2a4` or an opcode pattern like `DXYN`.
+    pub fn parse(text: &str) -> Result<Self, BreakpointError> {
+        let text = text.trim();
+        let (location, condition) = match text.strip_prefix("if ") {
+            Some(condition) => ("", Some(condition)),
+            None => match text.split_once(" if ") {
+                Some((location, condition)) => (location.trim(), Some(condition)),
+                None => (text, None),
+            },
+        };
+        let location = if location.is_empty() && condition.is_some() {
+            Location::Anywhere
+        } else if let Some(hex) = location.strip_prefix("0x") {
+            u16::from_str_radix(hex, 16)
+                .map(Location::Address)
+                .map_err(|_| BreakpointError::InvalidLocation)?
+        } else {
+            OpcodePattern::parse(location)
+                .map(Location::Opcode)
+                .ok_or(BreakpointError::InvalidLocation)?
+        };
+        let condition = condition
+            .map(Expr::parse)
+            .transpose()
+            .map_err(BreakpointError::Condition)?;
+        Ok(Self {
+            condition,
+            ..Self::new(location)
+        })
+    }
+
+    /// Whether the breakpoint triggers before the next instruction of `sys`.
+    pub fn is_hit(&self, sys: &System) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let at_location = match self.location {
+            Location::Address(address) => sys.program_counter == address,
+            Location::Opcode(pattern) => sys.fetch().is_ok_and(|opcode| pattern.matches(opcode)),
+            Location::Anywhere => true,
+        };
+        at_location
+            && self
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.is_true(sys))
+    }
+}
+
+/// Prints the breakpoint in the form `Breakpoint::parse` reads.
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.location {
+            Location::Address(address) => write!(f, "{:#06x}", address)?,
+            Location::Opcode(pattern) => write!(f, "{}", pattern)?,
+            Location::Anywhere => {}
+        }
+        if let Some(condition) = &self.condition {
+            if self.location != Location::Anywhere {
+                write!(f, " ")?;
+            }
+            write!(f, "if {}", condition)?;
+        }
+        Ok(())
+    }
+}
+
+/// Identifies a breakpoint within its debugger. Ids are never reused.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BreakpointId(u32);
+
+impl fmt::Display for BreakpointId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// Why the debugger handed control back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// A breakpoint triggered before the instruction at `address`, which hasn't run.
+    Breakpoint { id: BreakpointId, address: u16 },
+    /// The program counter reached the address given to `run_until`.
+    ReachedAddress,
+    /// A single step finished.
+    Stepped,
+    /// A whole frame ran, timers included.
+    FrameEnd,
+    /// The step budget of `run` or `run_until` ran out.
+    StepLimit,
+    /// The program stopped itself with 00FD.
+    Halted,
+    /// An FX0A instruction waits for a key that only the frontend can press.
+    WaitingForKey,
+    /// A step ran nothing because a draw waits for the end of the frame, see the
+    /// `display_wait` quirk. Stepping on reaches it.
+    WaitingForVblank,
+    /// The emulator failed. The faulty instruction hasn't run.
+    Error(EmuError),
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Breakpoint { id, address } => {
+                write!(f, "breakpoint {} at {:#06x}", id, address)
+            }
+            StopReason::ReachedAddress => write!(f, "reached address"),
+            StopReason::Stepped => write!(f, "stepped"),
+            StopReason::FrameEnd => write!(f, "end of frame"),
+            StopReason::StepLimit => write!(f, "step limit"),
+            StopReason::Halted => write!(f, "halted"),
+            StopReason::WaitingForKey => write!(f, "waiting for a key"),
+            StopReason::WaitingForVblank => write!(f, "waiting for the end of the frame"),
+            StopReason::Error(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Runs a `System` instruction by instruction, stopping at breakpoints.
+///
+/// Frames keep the timing of `System::run_frame`: the timers tick after every
+/// `instructions_per_frame` instructions, however the steps are split between calls.
+#[derive(Clone, Debug, Default)]
+pub struct Debugger {
+    breakpoints: Vec<(BreakpointId, Breakpoint)>,
+    next_id: u32,
+    /// Instructions run in the current frame.
+    frame_steps: u32,
+    /// Address the last breakpoint stop happened at, so resuming runs the instruction
+    /// there instead of stopping again.
+    resume_at: Option<u16>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> BreakpointId {
+        let id = BreakpointId(self.next_id);
+        self.next_id += 1;
+        self.breakpoints.push((id, breakpoint));
+        id
+    }
+
+    /// Returns the removed breakpoint, or `None` if there is none with this id.
+    pub fn remove_breakpoint(&mut self, id: BreakpointId) -> Option<Breakpoint> {
+        let index = self
+            .breakpoints
+            .iter()
+            .position(|(other, _)| *other == id)?;
+        Some(self.breakpoints.remove(index).1)
+    }
+
+    pub fn breakpoint_mut(&mut self, id: BreakpointId) -> Option<&mut Breakpoint> {
+        self.breakpoints
+            .iter_mut()
+            .find(|(other, _)| *other == id)
+            .map(|(_, breakpoint)| breakpoint)
+    }
+
+    /// Breakpoints in the order they were added.
+    pub fn breakpoints(&self) -> impl Iterator<Item = (BreakpointId, &Breakpoint)> {
+        self.breakpoints
+            .iter()
+            .map(|(id, breakpoint)| (*id, breakpoint))
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Runs one instruction, ignoring breakpoints. While a draw waits for the end of
+    /// the frame, the step runs nothing but still counts towards the frame.
+    pub fn step(&mut self, sys: &mut System) -> StopReason {
+        if sys.is_halted() {
+            return StopReason::Halted;
+        }
+        let blocked = sys.is_waiting_for_vblank();
+        match self.advance(sys) {
+            Ok(()) if blocked => StopReason::WaitingForVblank,
+            Ok(()) => StopReason::Stepped,
+            Err(err) => StopReason::Error(err),
+        }
+    }
+
+    /// Runs until the end of the current frame, or until a breakpoint triggers.
+    pub fn run_frame(&mut self, sys: &mut System) -> StopReason {
+        loop {
+            if let Some(stop) = self.check(sys, None) {
+                return stop;
+            }
+            if let Err(err) = self.advance(sys) {
+                return StopReason::Error(err);
+            }
+            if self.frame_steps == 0 {
+                return StopReason::FrameEnd;
+            }
+        }
+    }
+
+    /// Runs at most `max_steps` instructions, stopping early at breakpoints, on 00FD and
+    /// on FX0A.
+    pub fn run(&mut self, sys: &mut System, max_steps: u64) -> StopReason {
+        self.run_to(sys, None, max_steps)
+    }
+
+    /// Like `run`, but also stops once the program counter reaches `address`.
+    pub fn run_until(&mut self, sys: &mut System, address: u16, max_steps: u64) -> StopReason {
+        self.run_to(sys, Some(address), max_steps)
+    }
+
+    fn run_to(&mut self, sys: &mut System, target: Option<u16>, max_steps: u64) -> StopReason {
+        for _ in 0..max_steps {
+            if sys.is_halted() {
+                return StopReason::Halted;
+            }
+            if sys.is_waiting_for_key() {
+                return StopReason::WaitingForKey;
+            }
+            if let Some(stop) = self.check(sys, target) {
+                return stop;
+            }
+            if let Err(err) = self.advance(sys) {
+                return StopReason::Error(err);
+            }
+        }
+        StopReason::StepLimit
+    }
+
+    /// Looks for a reason to stop before the next instruction. Blocked systems don't
+    /// run anything, so they can't stop there.
+    fn check(&mut self, sys: &System, target: Option<u16>) -> Option<StopReason> {
+        if sys.is_halted() || sys.is_waiting_for_key() || sys.is_waiting_for_vblank() {
+            return None;
+        }
+        let address = sys.program_counter;
+        if self.resume_at.take() == Some(address) {
+            return None;
+        }
+        if target == Some(address) {
+            return Some(StopReason::ReachedAddress);
+        }
+        let (id, _) = self
+            .breakpoints
+            .iter()
+            .find(|(_, breakpoint)| breakpoint.is_hit(sys))?;
+        self.resume_at = Some(address);
+        Some(StopReason::Breakpoint { id: *id, address })
+    }
+
+    /// Runs one instruction slot of the frame, ticking the timers after the last one.
+    fn advance(&mut self, sys: &mut System) -> Result<(), EmuError> {
+        self.resume_at = None;
+        sys.step()?;
+        self.frame_steps += 1;
+        if self.frame_steps >= sys.instructions_per_frame {
+            self.frame_steps = 0;
+            sys.tick_timers();
+        }
+        Ok(())
+    }
+}
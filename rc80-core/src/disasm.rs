@@ -0,0 +1,249 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::{Instruction, SymbolMap, Variant};
+
+/// Assembly dialect used when rendering a disassembly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Syntax {
+    /// Octo's high-level syntax, e.g. `v3 := 0x10`.
+    #[default]
+    Octo,
+    /// The classic mnemonics from Cowgod's technical reference, e.g. `LD V3, 0x10`.
+    Cowgod,
+}
+
+/// What a disassembled address holds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Item {
+    Instruction(Instruction),
+    /// Bytes that don't form a valid instruction.
+    Data(Vec<u8>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    pub address: u16,
+    pub item: Item,
+}
+
+/// The result of a linear sweep over a byte range.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Disassembly {
+    pub lines: Vec<Line>,
+    /// Generated names for addresses targeted by jumps, calls and I loads.
+    pub labels: BTreeMap<u16, String>,
+}
+
+/// Disassembles `bytes` as if they were loaded at `origin`, two bytes at a time, or
+/// four for `F000 NNNN` on XO-CHIP. Words that don't decode to an instruction `variant`
+/// supports are kept as data.
+pub fn disassemble(bytes: &[u8], origin: u16, variant: Variant) -> Disassembly {
+    let word = |offset: usize| match bytes.get(offset..offset + 2) {
+        Some([high, low]) => Some((*high as u16) << 8 | *low as u16),
+        _ => None,
+    };
+    let mut lines = vec![];
+    let mut offset = 0;
+    while offset < bytes.len() {
+        let address = origin.wrapping_add(offset as u16);
+        let instruction = word(offset).and_then(|opcode| {
+            word(offset + 2)
+                .and_then(|operand| Instruction::decode_long(opcode, operand))
+                .or_else(|| Instruction::decode(opcode))
+                .filter(|instruction| variant.supports(*instruction))
+        });
+        let (item, size) = match instruction {
+            Some(instruction) => (Item::Instruction(instruction), instruction.size() as usize),
+            None => {
+                let end = (offset + 2).min(bytes.len());
+                (Item::Data(bytes[offset..end].to_vec()), end - offset)
+            }
+        };
+        lines.push(Line { address, item });
+        offset += size;
+    }
+
+    // Ordered by precedence, so a subroutine that is also jumped to keeps `sub`
+    #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    enum LabelKind {
+        Data,
+        Label,
+        Sub,
+    }
+
+    let mut kinds = BTreeMap::new();
+    for line in &lines {
+        let (target, kind) = match line.item {
+            Item::Instruction(Instruction::Call(target)) => (target, LabelKind::Sub),
+            Item::Instruction(Instruction::Jump(target))
+            | Item::Instruction(Instruction::JumpOffset(target)) => (target, LabelKind::Label),
+            Item::Instruction(Instruction::LoadI(target))
+            | Item::Instruction(Instruction::LoadLongI(target)) => (target, LabelKind::Data),
+            _ => continue,
+        };
+        // Only label addresses that start a line, anything else has nowhere to go
+        if lines
+            .binary_search_by_key(&target, |line| line.address)
+            .is_err()
+        {
+            continue;
+        }
+        let entry = kinds.entry(target).or_insert(kind);
+        *entry = kind.max(*entry);
+    }
+    let labels = kinds
+        .into_iter()
+        .map(|(target, kind)| {
+            let prefix = match kind {
+                LabelKind::Data => "data",
+                LabelKind::Label => "label",
+                LabelKind::Sub => "sub",
+            };
+            (target, format!("{}_{:03x}", prefix, target))
+        })
+        .collect();
+
+    Disassembly { lines, labels }
+}
+
+impl Disassembly {
+    /// Replaces generated label names with the names from `symbols`, and labels any
+    /// other line that a symbol points at.
+    pub fn apply_symbols(&mut self, symbols: &SymbolMap) {
+        for line in &self.lines {
+            if let Some(name) = symbols.name_at(line.address) {
+                self.labels.insert(line.address, name.to_owned());
+            }
+        }
+    }
+
+    /// Renders the listing, one line per instruction with its address and raw bytes in
+    /// a trailing comment.
+    pub fn render(&self, syntax: Syntax) -> String {
+        let comment = match syntax {
+            Syntax::Octo => '#',
+            Syntax::Cowgod => ';',
+        };
+        let mut out = String::new();
+        for line in &self.lines {
+            if let Some(label) = self.labels.get(&line.address) {
+                match syntax {
+                    Syntax::Octo => writeln!(out, ": {}", label).unwrap(),
+                    Syntax::Cowgod => writeln!(out, "{}:", label).unwrap(),
+                }
+            }
+            let (text, bytes) = match &line.item {
+                Item::Instruction(instruction) => {
+                    let text = match syntax {
+                        Syntax::Octo => self.octo(*instruction),
+                        Syntax::Cowgod => self.cowgod(*instruction),
+                    };
+                    let raw = match instruction {
+                        Instruction::LoadLongI(target) => {
+                            format!("{:04x} {:04x}", instruction.encode(), target)
+                        }
+                        _ => format!("{:04x}", instruction.encode()),
+                    };
+                    (text, raw)
+                }
+                Item::Data(bytes) => {
+                    let hex: Vec<_> = bytes.iter().map(|b| format!("{:#04x}", b)).collect();
+                    let text = match syntax {
+                        Syntax::Octo => hex.join(" "),
+                        Syntax::Cowgod => format!("DB {}", hex.join(", ")),
+                    };
+                    let raw: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                    (text, raw)
+                }
+            };
+            writeln!(
+                out,
+                "    {:<24}{} {:03x}: {}",
+                text, comment, line.address, bytes
+            )
+            .unwrap();
+        }
+        out
+    }
+
+    fn target(&self, address: u16) -> String {
+        match self.labels.get(&address) {
+            Some(label) => label.clone(),
+            None => format!("{:#05x}", address),
+        }
+    }
+
+    fn cowgod(&self, instruction: Instruction) -> String {
+        match instruction {
+            Instruction::Jump(target) => format!("JP {}", self.target(target)),
+            Instruction::Call(target) => format!("CALL {}", self.target(target)),
+            Instruction::LoadI(target) => format!("LD I, {}", self.target(target)),
+            Instruction::LoadLongI(target) => format!("LD I, {}", self.target(target)),
+            Instruction::JumpOffset(target) => format!("JP V0, {}", self.target(target)),
+            _ => instruction.to_string(),
+        }
+    }
+
+    fn octo(&self, instruction: Instruction) -> String {
+        match instruction {
+            // Octo has no mnemonic for native calls, so emit the raw bytes
+            Instruction::Sys(address) => format!("{:#04x} {:#04x}", address >> 8, address & 0xFF),
+            Instruction::ClearScreen => "clear".to_owned(),
+            Instruction::Return => "return".to_owned(),
+            Instruction::ScrollDown(n) => format!("scroll-down {}", n),
+            Instruction::ScrollUp(n) => format!("scroll-up {}", n),
+            Instruction::ScrollRight => "scroll-right".to_owned(),
+            Instruction::ScrollLeft => "scroll-left".to_owned(),
+            Instruction::Exit => "exit".to_owned(),
+            Instruction::LowRes => "lores".to_owned(),
+            Instruction::HighRes => "hires".to_owned(),
+            Instruction::Jump(target) => format!("jump {}", self.target(target)),
+            Instruction::Call(target) => match self.labels.get(&target) {
+                Some(label) => label.clone(),
+                None => format!(":call {:#05x}", target),
+            },
+            Instruction::SkipEqByte { x, byte } => format!("if v{:x} != {:#04x} then", x, byte),
+            Instruction::SkipNeByte { x, byte } => format!("if v{:x} == {:#04x} then", x, byte),
+            Instruction::SkipEqReg { x, y } => format!("if v{:x} != v{:x} then", x, y),
+            Instruction::StoreRange { x, y } => format!("save v{:x} - v{:x}", x, y),
+            Instruction::LoadRange { x, y } => format!("load v{:x} - v{:x}", x, y),
+            Instruction::LoadByte { x, byte } => format!("v{:x} := {:#04x}", x, byte),
+            Instruction::AddByte { x, byte } => format!("v{:x} += {:#04x}", x, byte),
+            Instruction::LoadReg { x, y } => format!("v{:x} := v{:x}", x, y),
+            Instruction::Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+            Instruction::And { x, y } => format!("v{:x} &= v{:x}", x, y),
+            Instruction::Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+            Instruction::AddReg { x, y } => format!("v{:x} += v{:x}", x, y),
+            Instruction::Sub { x, y } => format!("v{:x} -= v{:x}", x, y),
+            Instruction::ShiftRight { x, y } => format!("v{:x} >>= v{:x}", x, y),
+            Instruction::SubN { x, y } => format!("v{:x} =- v{:x}", x, y),
+            Instruction::ShiftLeft { x, y } => format!("v{:x} <<= v{:x}", x, y),
+            Instruction::SkipNeReg { x, y } => format!("if v{:x} == v{:x} then", x, y),
+            Instruction::LoadI(target) => format!("i := {}", self.target(target)),
+            Instruction::JumpOffset(target) => format!("jump0 {}", self.target(target)),
+            Instruction::Random { x, byte } => format!("v{:x} := random {:#04x}", x, byte),
+            Instruction::Draw { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+            Instruction::SkipKey { x } => format!("if v{:x} -key then", x),
+            Instruction::SkipNotKey { x } => format!("if v{:x} key then", x),
+            Instruction::LoadLongI(target) => format!("i := long {}", self.target(target)),
+            // Octo only has masks for the two planes, so emit the raw bytes for the rest
+            Instruction::SelectPlanes(n) if n > 3 => format!("{:#04x} {:#04x}", 0xF0 | n, 0x01),
+            Instruction::SelectPlanes(n) => format!("plane {}", n),
+            Instruction::LoadAudio => "audio".to_owned(),
+            Instruction::LoadDelay { x } => format!("v{:x} := delay", x),
+            Instruction::WaitKey { x } => format!("v{:x} := key", x),
+            Instruction::SetDelay { x } => format!("delay := v{:x}", x),
+            Instruction::SetSound { x } => format!("buzzer := v{:x}", x),
+            Instruction::AddI { x } => format!("i += v{:x}", x),
+            Instruction::LoadFont { x } => format!("i := hex v{:x}", x),
+            Instruction::LoadBigFont { x } => format!("i := bighex v{:x}", x),
+            Instruction::StoreBcd { x } => format!("bcd v{:x}", x),
+            Instruction::SetPitch { x } => format!("pitch := v{:x}", x),
+            Instruction::StoreRegs { x } => format!("save v{:x}", x),
+            Instruction::LoadRegs { x } => format!("load v{:x}", x),
+            Instruction::StoreFlags { x } => format!("saveflags v{:x}", x),
+            Instruction::LoadFlags { x } => format!("loadflags v{:x}", x),
+        }
+    }
+}
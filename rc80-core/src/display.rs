@@ -0,0 +1,313 @@
+/// Widest display supported, the number of bits in a packed row.
+pub const MAX_DISPLAY_WIDTH: usize = u128::BITS as usize;
+
+/// A monochrome screen with one or more bitplanes.
+///
+/// Each plane is stored as one packed `u128` per row, with the leftmost pixel in the
+/// most significant bit, so sprites can be drawn a whole row at a time. The per-pixel
+/// accessors combine the planes into one bit per plane, so on a two plane display a
+/// pixel lit on both planes has the value 0b11.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "DisplayRepr", try_from = "DisplayRepr")
+)]
+pub struct Display {
+    width: usize,
+    height: usize,
+    plane_count: usize,
+    /// `height` rows for the first plane, followed by those of the next.
+    rows: Vec<u128>,
+}
+
+impl Display {
+    /// Creates a blank display. At most `MAX_DISPLAY_WIDTH` pixels wide and 8 planes are
+    /// supported.
+    pub fn new(width: usize, height: usize, plane_count: usize) -> Self {
+        assert!(
+            width <= MAX_DISPLAY_WIDTH,
+            "display is wider than {} pixels",
+            MAX_DISPLAY_WIDTH
+        );
+        assert!(
+            plane_count <= 8,
+            "{} planes do not fit in a pixel",
+            plane_count
+        );
+        Self {
+            width,
+            height,
+            plane_count,
+            rows: vec![0; height * plane_count],
+        }
+    }
+
+    /// Builds a display from one byte per pixel, row by row, as returned by
+    /// [`Display::to_pixels`]. Bits above `plane_count` are ignored.
+    pub fn from_pixels(width: usize, height: usize, plane_count: usize, pixels: &[u8]) -> Self {
+        assert_eq!(pixels.len(), width * height, "wrong number of pixels");
+        let mut display = Self::new(width, height, plane_count);
+        for (index, pixel) in pixels.iter().enumerate() {
+            let (x, y) = (index % width, index / width);
+            for plane in 0..plane_count {
+                if pixel >> plane & 1 == 1 {
+                    display.rows[plane * height + y] |= display.column_bit(x);
+                }
+            }
+        }
+        display
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn plane_count(&self) -> usize {
+        self.plane_count
+    }
+
+    /// Returns the planes lit at (`x`, `y`) as a bitmask.
+    pub fn get(&self, x: usize, y: usize) -> u8 {
+        assert!(x < self.width && y < self.height, "pixel out of bounds");
+        let bit = self.column_bit(x);
+        (0..self.plane_count)
+            .filter(|plane| self.rows[plane * self.height + y] & bit != 0)
+            .fold(0, |planes, plane| planes | 1 << plane)
+    }
+
+    /// Whether the pixel at (`x`, `y`) is lit on `plane`, counting from 0.
+    pub fn is_lit(&self, x: usize, y: usize, plane: usize) -> bool {
+        self.row(plane, y) & self.column_bit(x) != 0
+    }
+
+    /// Returns row `y` of `plane` packed into bits, the leftmost pixel in the most
+    /// significant bit. Bits past the display width are always zero.
+    pub fn row(&self, plane: usize, y: usize) -> u128 {
+        assert!(
+            plane < self.plane_count && y < self.height,
+            "row out of bounds"
+        );
+        self.rows[plane * self.height + y]
+    }
+
+    /// Iterates over every pixel, row by row, as `(x, y, planes)`.
+    pub fn iter(&self) -> impl Iterator<Item = (usize, usize, u8)> + '_ {
+        (0..self.height).flat_map(move |y| (0..self.width).map(move |x| (x, y, self.get(x, y))))
+    }
+
+    /// Iterates over the pixels lit on at least one plane, row by row.
+    pub fn lit_pixels(&self) -> impl Iterator<Item = (usize, usize, u8)> + '_ {
+        self.iter().filter(|(_, _, planes)| *planes != 0)
+    }
+
+    /// Unpacks the display into one byte per pixel, row by row.
+    pub fn to_pixels(&self) -> Vec<u8> {
+        self.iter().map(|(_, _, planes)| planes).collect()
+    }
+
+    /// The packed rows of every plane, one plane after the other.
+    pub(crate) fn packed_rows(&self) -> &[u128] {
+        &self.rows
+    }
+
+    /// Rebuilds a display from `packed_rows`, or `None` if the rows don't fit the
+    /// dimensions.
+    pub(crate) fn from_packed_rows(
+        width: usize,
+        height: usize,
+        plane_count: usize,
+        rows: Vec<u128>,
+    ) -> Option<Self> {
+        if width > MAX_DISPLAY_WIDTH || plane_count > 8 || rows.len() != height * plane_count {
+            return None;
+        }
+        let display = Self {
+            width,
+            height,
+            plane_count,
+            rows,
+        };
+        let mask = display.row_mask();
+        display
+            .rows
+            .iter()
+            .all(|row| row & !mask == 0)
+            .then_some(display)
+    }
+
+    /// Changes the resolution, blanking the whole display.
+    pub(crate) fn resize(&mut self, width: usize, height: usize) {
+        assert!(width <= MAX_DISPLAY_WIDTH, "display is too wide");
+        self.width = width;
+        self.height = height;
+        self.rows.clear();
+        self.rows.resize(height * self.plane_count, 0);
+    }
+
+    /// Unlights `planes` everywhere.
+    pub(crate) fn clear(&mut self, planes: u8) {
+        for plane in self.selected(planes) {
+            self.plane_rows(plane).fill(0);
+        }
+    }
+
+    /// XORs `bits`, a sprite row `bits_width` pixels wide, onto row `y` of `plane`
+    /// starting at column `x`. Pixels past the right edge wrap around to the left if
+    /// `wrap` is set and are dropped otherwise. Returns whether any lit pixel was
+    /// flipped off.
+    pub(crate) fn xor_row(
+        &mut self,
+        plane: usize,
+        x: usize,
+        y: usize,
+        bits: u128,
+        bits_width: usize,
+        wrap: bool,
+    ) -> bool {
+        let aligned = bits << (MAX_DISPLAY_WIDTH - bits_width);
+        let mut word = aligned >> x;
+        if wrap {
+            word |= aligned.checked_shl((self.width - x) as u32).unwrap_or(0);
+        }
+        word &= self.row_mask();
+        let row = &mut self.rows[plane * self.height + y];
+        let collided = *row & word != 0;
+        *row ^= word;
+        collided
+    }
+
+    /// Moves `planes` by `dx` pixels right and `dy` pixels down, filling the uncovered
+    /// area with unlit pixels.
+    pub(crate) fn scroll(&mut self, dx: isize, dy: isize, planes: u8) {
+        let height = self.height;
+        let mask = self.row_mask();
+        for plane in self.selected(planes) {
+            let rows = self.plane_rows(plane);
+            let shift = dy.unsigned_abs().min(height);
+            if dy > 0 {
+                rows.copy_within(..height - shift, shift);
+                rows[..shift].fill(0);
+            } else if dy < 0 {
+                rows.copy_within(shift.., 0);
+                rows[height - shift..].fill(0);
+            }
+            let shift = dx.unsigned_abs() as u32;
+            for row in rows {
+                let moved = if dx >= 0 {
+                    row.checked_shr(shift)
+                } else {
+                    row.checked_shl(shift)
+                };
+                *row = moved.unwrap_or(0) & mask;
+            }
+        }
+    }
+
+    fn column_bit(&self, x: usize) -> u128 {
+        assert!(x < self.width, "column out of bounds");
+        1 << (MAX_DISPLAY_WIDTH - 1 - x)
+    }
+
+    /// The bits of a packed row that are on screen.
+    fn row_mask(&self) -> u128 {
+        (!0u128)
+            .checked_shl((MAX_DISPLAY_WIDTH - self.width) as u32)
+            .unwrap_or(0)
+    }
+
+    fn plane_rows(&mut self, plane: usize) -> &mut [u128] {
+        &mut self.rows[plane * self.height..][..self.height]
+    }
+
+    /// The planes in the bitmask `planes` that this display has.
+    fn selected(&self, planes: u8) -> impl Iterator<Item = usize> {
+        (0..self.plane_count).filter(move |plane| planes >> plane & 1 == 1)
+    }
+}
+
+/// Serialized form of a display, readable in text formats: each plane is a list of
+/// rows, with `#` for lit pixels and `.` for unlit ones.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "Display")]
+struct DisplayRepr {
+    width: usize,
+    height: usize,
+    planes: Vec<Vec<String>>,
+}
+
+#[cfg(feature = "serde")]
+impl From<Display> for DisplayRepr {
+    fn from(display: Display) -> Self {
+        let planes = (0..display.plane_count)
+            .map(|plane| {
+                (0..display.height)
+                    .map(|y| {
+                        (0..display.width)
+                            .map(|x| {
+                                if display.is_lit(x, y, plane) {
+                                    '#'
+                                } else {
+                                    '.'
+                                }
+                            })
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        Self {
+            width: display.width,
+            height: display.height,
+            planes,
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<DisplayRepr> for Display {
+    type Error = String;
+
+    fn try_from(repr: DisplayRepr) -> Result<Self, Self::Error> {
+        // Checked before packing, which shifts by the pixel position
+        if repr.width > MAX_DISPLAY_WIDTH {
+            return Err(format!(
+                "display is wider than {} pixels",
+                MAX_DISPLAY_WIDTH
+            ));
+        }
+        if repr.planes.len() > 8 {
+            return Err(format!(
+                "{} planes do not fit in a pixel",
+                repr.planes.len()
+            ));
+        }
+        if repr.planes.iter().any(|plane| plane.len() != repr.height) {
+            return Err(format!("expected {} rows per plane", repr.height));
+        }
+        let mut rows = Vec::with_capacity(repr.height * repr.planes.len());
+        for plane in &repr.planes {
+            for row in plane {
+                if row.chars().count() != repr.width {
+                    return Err(format!("expected rows of {} pixels", repr.width));
+                }
+                let mut bits = 0;
+                for (x, pixel) in row.chars().enumerate() {
+                    match pixel {
+                        '#' => bits |= 1 << (MAX_DISPLAY_WIDTH - 1 - x),
+                        '.' => {}
+                        other => return Err(format!("unexpected pixel '{}'", other)),
+                    }
+                }
+                rows.push(bits);
+            }
+        }
+        Display::from_packed_rows(repr.width, repr.height, repr.planes.len(), rows)
+            .ok_or_else(|| "invalid display dimensions".to_owned())
+    }
+}
@@ -0,0 +1,173 @@
+use std::fmt;
+
+/// Errors raised while loading or running a program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmuError {
+    /// The ROM does not fit in the memory available to programs.
+    RomTooLarge { size: usize, max: usize },
+    /// A subroutine call was made with a full stack.
+    StackOverflow { address: u16 },
+    /// A return was made with an empty stack.
+    StackUnderflow { address: u16 },
+    /// An instruction accessed memory past the end of the address space.
+    OutOfBounds { address: usize },
+    /// The fetched word does not decode to a known instruction.
+    InvalidOpcode { address: u16, opcode: u16 },
+    /// The font doesn't fit below the program at this address.
+    FontOutOfRange { address: u16 },
+}
+
+impl fmt::Display for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmuError::RomTooLarge { size, max } => {
+                write!(f, "ROM is {} bytes but at most {} fit in memory", size, max)
+            }
+            EmuError::StackOverflow { address } => {
+                write!(f, "stack overflow on call at {:#05x}", address)
+            }
+            EmuError::StackUnderflow { address } => {
+                write!(f, "stack underflow on return at {:#05x}", address)
+            }
+            EmuError::OutOfBounds { address } => {
+                write!(f, "memory access out of bounds at {:#x}", address)
+            }
+            EmuError::InvalidOpcode { address, opcode } => {
+                write!(f, "invalid opcode {:#06x} at {:#05x}", opcode, address)
+            }
+            EmuError::FontOutOfRange { address } => {
+                write!(f, "font at {:#05x} does not fit below the program", address)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EmuError {}
+
+/// Errors raised while restoring a save state.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateError {
+    /// The data doesn't start with the save state magic bytes.
+    NotAState,
+    /// The state was written by a newer version of the format.
+    UnsupportedVersion { version: u16 },
+    /// The data ends in the middle of the state.
+    Truncated,
+    /// A field holds a value the machine can't be in.
+    Invalid { field: &'static str },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::NotAState => write!(f, "not a save state"),
+            StateError::UnsupportedVersion { version } => {
+                write!(f, "save state version {} is not supported", version)
+            }
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Invalid { field } => write!(f, "save state has an invalid {}", field),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Errors raised while reading or playing back an input movie.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MovieError {
+    /// The text form is malformed at this 1-based line.
+    Parse { line: usize },
+    /// The ROM differs from the one the movie was recorded on.
+    RomMismatch,
+    /// The emulator failed during `frame`, counting from 0.
+    Emu { frame: usize, error: EmuError },
+    /// `frame` is past the end of the movie.
+    PastEnd { frame: usize },
+    /// The state after `frame` differs from the recording.
+    Desync {
+        frame: usize,
+        expected: u64,
+        actual: u64,
+    },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::Parse { line } => write!(f, "malformed movie at line {}", line),
+            MovieError::RomMismatch => write!(f, "movie was recorded on a different ROM"),
+            MovieError::Emu { frame, error } => write!(f, "frame {}: {}", frame, error),
+            MovieError::PastEnd { frame } => write!(f, "movie has no frame {}", frame),
+            MovieError::Desync {
+                frame,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "desync at frame {}: expected state {:016x}, got {:016x}",
+                frame, expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+/// Errors raised while parsing an expression. Positions are byte offsets into the text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExprError {
+    /// A character that doesn't start any token.
+    UnexpectedChar { position: usize },
+    /// A token where the grammar doesn't allow one.
+    UnexpectedToken { position: usize },
+    /// The text ends in the middle of the expression.
+    UnexpectedEnd,
+    /// A number that is malformed or too large.
+    InvalidNumber { position: usize },
+    /// A name that isn't a register.
+    UnknownName { position: usize },
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExprError::UnexpectedChar { position } => {
+                write!(f, "unexpected character at column {}", position + 1)
+            }
+            ExprError::UnexpectedToken { position } => {
+                write!(f, "unexpected token at column {}", position + 1)
+            }
+            ExprError::UnexpectedEnd => write!(f, "unexpected end of expression"),
+            ExprError::InvalidNumber { position } => {
+                write!(f, "invalid number at column {}", position + 1)
+            }
+            ExprError::UnknownName { position } => {
+                write!(f, "unknown register at column {}", position + 1)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+/// Errors raised while parsing a breakpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BreakpointError {
+    /// Neither a `0x` address nor a four character opcode pattern.
+    InvalidLocation,
+    /// The condition after `if` doesn't parse.
+    Condition(ExprError),
+}
+
+impl fmt::Display for BreakpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BreakpointError::InvalidLocation => {
+                write!(f, "expected an 0x address or an opcode pattern like DXYN")
+            }
+            BreakpointError::Condition(err) => write!(f, "in condition: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for BreakpointError {}
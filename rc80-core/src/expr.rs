@@ -0,0 +1,263 @@
+//! A small expression language over the machine state, for conditional breakpoints.
+//!
+//! ```text
+//! V3 == 0x10 && [I] != 0
+//! ```
+//!
+//! Operands are the registers `V0` to `VF`, `I`, `PC`, `SP`, `DT` (delay timer) and
+//! `ST` (sound timer), numbers in decimal or in hex with `0x`, and `[addr]` for the
+//! memory byte at `addr`, which reads as 0 past the end of memory. Operators, from
+//! loosest to tightest binding: `||`, `&&`, the comparisons `==` `!=` `<` `<=` `>` `>=`,
+//! `|`, `^`, `&`, `+` and `-`, then the unary `!` and `-`. Parentheses group.
+//!
+//! Values are signed 64-bit integers. Comparisons and logic operators give 1 or 0, and
+//! any value other than 0 counts as true.
+
+use std::fmt;
+
+use crate::error::ExprError;
+use crate::System;
+
+/// Symbols, longest first so `<=` isn't read as `<`.
+const SYMBOLS: [&str; 18] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<", ">", "|", "^", "&", "+", "-", "!", "(", ")", "[", "]",
+];
+/// Binary operators by precedence level, loosest first.
+const BINARY_LEVELS: [&[&str]; 7] = [
+    &["||"],
+    &["&&"],
+    &["==", "!=", "<", "<=", ">", ">="],
+    &["|"],
+    &["^"],
+    &["&"],
+    &["+", "-"],
+];
+
+/// A parsed expression, see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expr {
+    source: String,
+    root: Node,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Register {
+    V(u8),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Node {
+    Number(i64),
+    Register(Register),
+    Memory(Box<Node>),
+    Unary(&'static str, Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Token {
+    Number(i64),
+    Register(Register),
+    Symbol(&'static str),
+}
+
+impl Expr {
+    pub fn parse(source: &str) -> Result<Self, ExprError> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            index: 0,
+        };
+        let root = parser.binary(0)?;
+        if let Some((position, _)) = parser.peek() {
+            return Err(ExprError::UnexpectedToken { position });
+        }
+        Ok(Self {
+            source: source.trim().to_owned(),
+            root,
+        })
+    }
+
+    /// Computes the value of the expression on the current state of `sys`.
+    pub fn eval(&self, sys: &System) -> i64 {
+        eval(&self.root, sys)
+    }
+
+    /// Whether the expression evaluates to anything but 0.
+    pub fn is_true(&self, sys: &System) -> bool {
+        self.eval(sys) != 0
+    }
+}
+
+/// Prints the expression as it was written.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, ExprError> {
+    let mut tokens = vec![];
+    let mut position = 0;
+    while position < source.len() {
+        let rest = &source[position..];
+        let first = rest.chars().next().unwrap();
+        if first.is_whitespace() {
+            position += first.len_utf8();
+            continue;
+        }
+        if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+            tokens.push((position, Token::Symbol(symbol)));
+            position += symbol.len();
+            continue;
+        }
+        if !first.is_ascii_alphanumeric() {
+            return Err(ExprError::UnexpectedChar { position });
+        }
+        let word_len = rest
+            .find(|c: char| !c.is_ascii_alphanumeric())
+            .unwrap_or(rest.len());
+        let word = &rest[..word_len];
+        let token = if first.is_ascii_digit() {
+            let number = match word.strip_prefix("0x") {
+                Some(hex) => i64::from_str_radix(hex, 16),
+                None => word.parse(),
+            };
+            Token::Number(number.map_err(|_| ExprError::InvalidNumber { position })?)
+        } else {
+            Token::Register(register(word).ok_or(ExprError::UnknownName { position })?)
+        };
+        tokens.push((position, token));
+        position += word_len;
+    }
+    Ok(tokens)
+}
+
+fn register(name: &str) -> Option<Register> {
+    let name = name.to_ascii_uppercase();
+    let register = match name.as_str() {
+        "I" => Register::I,
+        "PC" => Register::Pc,
+        "SP" => Register::Sp,
+        "DT" => Register::Dt,
+        "ST" => Register::St,
+        _ => {
+            let index = name.strip_prefix('V')?;
+            if index.len() != 1 {
+                return None;
+            }
+            Register::V(u8::from_str_radix(index, 16).ok()?)
+        }
+    };
+    Some(register)
+}
+
+struct Parser<'a> {
+    tokens: &'a [(usize, Token)],
+    index: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<(usize, Token)> {
+        self.tokens.get(self.index).copied()
+    }
+
+    fn next(&mut self) -> Result<(usize, Token), ExprError> {
+        let token = self.peek().ok_or(ExprError::UnexpectedEnd)?;
+        self.index += 1;
+        Ok(token)
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), ExprError> {
+        match self.next()? {
+            (_, Token::Symbol(found)) if found == symbol => Ok(()),
+            (position, _) => Err(ExprError::UnexpectedToken { position }),
+        }
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Node, ExprError> {
+        let Some(operators) = BINARY_LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut lhs = self.binary(level + 1)?;
+        while let Some((_, Token::Symbol(symbol))) = self.peek() {
+            if !operators.contains(&symbol) {
+                break;
+            }
+            self.index += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = Node::Binary(symbol, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Node, ExprError> {
+        match self.next()? {
+            (_, Token::Symbol(symbol @ ("!" | "-"))) => {
+                Ok(Node::Unary(symbol, Box::new(self.unary()?)))
+            }
+            (_, Token::Symbol("(")) => {
+                let inner = self.binary(0)?;
+                self.expect(")")?;
+                Ok(inner)
+            }
+            (_, Token::Symbol("[")) => {
+                let address = self.binary(0)?;
+                self.expect("]")?;
+                Ok(Node::Memory(Box::new(address)))
+            }
+            (_, Token::Number(number)) => Ok(Node::Number(number)),
+            (_, Token::Register(register)) => Ok(Node::Register(register)),
+            (position, Token::Symbol(_)) => Err(ExprError::UnexpectedToken { position }),
+        }
+    }
+}
+
+fn eval(node: &Node, sys: &System) -> i64 {
+    match node {
+        Node::Number(number) => *number,
+        Node::Register(register) => match register {
+            Register::V(index) => sys.v_registers[*index as usize] as i64,
+            Register::I => sys.i_register as i64,
+            Register::Pc => sys.program_counter as i64,
+            Register::Sp => sys.stack_pointer as i64,
+            Register::Dt => sys.time_register as i64,
+            Register::St => sys.sound_register as i64,
+        },
+        Node::Memory(address) => usize::try_from(eval(address, sys))
+            .ok()
+            .and_then(|address| sys.memory.get(address))
+            .map_or(0, |byte| *byte as i64),
+        Node::Unary(operator, operand) => {
+            let value = eval(operand, sys);
+            match *operator {
+                "!" => (value == 0) as i64,
+                _ => value.wrapping_neg(),
+            }
+        }
+        Node::Binary("||", lhs, rhs) => (eval(lhs, sys) != 0 || eval(rhs, sys) != 0) as i64,
+        Node::Binary("&&", lhs, rhs) => (eval(lhs, sys) != 0 && eval(rhs, sys) != 0) as i64,
+        Node::Binary(operator, lhs, rhs) => {
+            let (lhs, rhs) = (eval(lhs, sys), eval(rhs, sys));
+            match *operator {
+                "==" => (lhs == rhs) as i64,
+                "!=" => (lhs != rhs) as i64,
+                "<" => (lhs < rhs) as i64,
+                "<=" => (lhs <= rhs) as i64,
+                ">" => (lhs > rhs) as i64,
+                ">=" => (lhs >= rhs) as i64,
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                _ => unreachable!("unknown operator {}", operator),
+            }
+        }
+    }
+}
@@ -0,0 +1,331 @@
+use std::fmt;
+
+/// A decoded CHIP-8 instruction. Register operands are indices into the V registers,
+/// addresses are 12 bits wide except for the XO-CHIP long load.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    /// `0NNN`: call a native machine code routine.
+    Sys(u16),
+    /// `00E0`: clear the screen.
+    ClearScreen,
+    /// `00EE`: return from a subroutine.
+    Return,
+    /// `00CN`: scroll the display down by N pixels. SUPER-CHIP.
+    ScrollDown(u8),
+    /// `00DN`: scroll the display up by N pixels. XO-CHIP.
+    ScrollUp(u8),
+    /// `00FB`: scroll the display right by 4 pixels. SUPER-CHIP.
+    ScrollRight,
+    /// `00FC`: scroll the display left by 4 pixels. SUPER-CHIP.
+    ScrollLeft,
+    /// `00FD`: exit the interpreter. SUPER-CHIP.
+    Exit,
+    /// `00FE`: switch to 64x32 low resolution mode. SUPER-CHIP.
+    LowRes,
+    /// `00FF`: switch to 128x64 high resolution mode. SUPER-CHIP.
+    HighRes,
+    /// `1NNN`: jump to NNN.
+    Jump(u16),
+    /// `2NNN`: call the subroutine at NNN.
+    Call(u16),
+    /// `3XNN`: skip the next instruction if VX == NN.
+    SkipEqByte { x: u8, byte: u8 },
+    /// `4XNN`: skip the next instruction if VX != NN.
+    SkipNeByte { x: u8, byte: u8 },
+    /// `5XY0`: skip the next instruction if VX == VY.
+    SkipEqReg { x: u8, y: u8 },
+    /// `5XY2`: store VX through VY at I, in either order. XO-CHIP.
+    StoreRange { x: u8, y: u8 },
+    /// `5XY3`: load VX through VY from I, in either order. XO-CHIP.
+    LoadRange { x: u8, y: u8 },
+    /// `6XNN`: VX = NN.
+    LoadByte { x: u8, byte: u8 },
+    /// `7XNN`: VX += NN, without touching VF.
+    AddByte { x: u8, byte: u8 },
+    /// `8XY0`: VX = VY.
+    LoadReg { x: u8, y: u8 },
+    /// `8XY1`: VX |= VY.
+    Or { x: u8, y: u8 },
+    /// `8XY2`: VX &= VY.
+    And { x: u8, y: u8 },
+    /// `8XY3`: VX ^= VY.
+    Xor { x: u8, y: u8 },
+    /// `8XY4`: VX += VY, VF = carry.
+    AddReg { x: u8, y: u8 },
+    /// `8XY5`: VX -= VY.
+    Sub { x: u8, y: u8 },
+    /// `8XY6`: shift right by one, VF = bit shifted out.
+    ShiftRight { x: u8, y: u8 },
+    /// `8XY7`: VX = VY - VX.
+    SubN { x: u8, y: u8 },
+    /// `8XYE`: shift left by one, VF = bit shifted out.
+    ShiftLeft { x: u8, y: u8 },
+    /// `9XY0`: skip the next instruction if VX != VY.
+    SkipNeReg { x: u8, y: u8 },
+    /// `ANNN`: I = NNN.
+    LoadI(u16),
+    /// `BNNN`: jump to NNN + V0 (or XNN + VX, depending on quirks).
+    JumpOffset(u16),
+    /// `CXNN`: VX = random byte & NN.
+    Random { x: u8, byte: u8 },
+    /// `DXYN`: draw an N byte sprite from I at (VX, VY). With N = 0, SUPER-CHIP draws a
+    /// 16x16 sprite.
+    Draw { x: u8, y: u8, n: u8 },
+    /// `EX9E`: skip the next instruction if key VX is pressed.
+    SkipKey { x: u8 },
+    /// `EXA1`: skip the next instruction if key VX is not pressed.
+    SkipNotKey { x: u8 },
+    /// `F000 NNNN`: I = NNNN, a 16 bit address in the following word. XO-CHIP.
+    LoadLongI(u16),
+    /// `FN01`: select the bitplanes N that drawing, clearing and scrolling affect.
+    /// XO-CHIP.
+    SelectPlanes(u8),
+    /// `F002`: load the 16 byte audio pattern from I. XO-CHIP.
+    LoadAudio,
+    /// `FX07`: VX = delay timer.
+    LoadDelay { x: u8 },
+    /// `FX0A`: wait for a key and store it in VX.
+    WaitKey { x: u8 },
+    /// `FX15`: delay timer = VX.
+    SetDelay { x: u8 },
+    /// `FX18`: sound timer = VX.
+    SetSound { x: u8 },
+    /// `FX1E`: I += VX.
+    AddI { x: u8 },
+    /// `FX29`: I = address of the font sprite for digit VX.
+    LoadFont { x: u8 },
+    /// `FX30`: I = address of the big font sprite for digit VX. SUPER-CHIP.
+    LoadBigFont { x: u8 },
+    /// `FX3A`: audio pattern playback pitch = VX. XO-CHIP.
+    SetPitch { x: u8 },
+    /// `FX33`: store the BCD digits of VX at I, I+1 and I+2.
+    StoreBcd { x: u8 },
+    /// `FX55`: store V0 through VX at I.
+    StoreRegs { x: u8 },
+    /// `FX65`: load V0 through VX from I.
+    LoadRegs { x: u8 },
+    /// `FX75`: store V0 through VX in the RPL user flags. SUPER-CHIP.
+    StoreFlags { x: u8 },
+    /// `FX85`: load V0 through VX from the RPL user flags. SUPER-CHIP.
+    LoadFlags { x: u8 },
+}
+
+/// First word of the four byte XO-CHIP `F000 NNNN` instruction.
+pub const LONG_LOAD_I: u16 = 0xF000;
+
+impl Instruction {
+    /// Decodes a big-endian instruction word, returning `None` if it isn't a valid
+    /// instruction. `F000` needs its second word, see [`Instruction::decode_long`].
+    pub fn decode(opcode: u16) -> Option<Self> {
+        let x = (opcode >> 8 & 0xF) as u8;
+        let y = (opcode >> 4 & 0xF) as u8;
+        let n = (opcode & 0xF) as u8;
+        let byte = (opcode & 0xFF) as u8;
+        let address = opcode & 0x0FFF;
+        let instruction = match opcode >> 12 {
+            0x0 => match opcode {
+                0x00E0 => Instruction::ClearScreen,
+                0x00EE => Instruction::Return,
+                0x00C0..=0x00CF => Instruction::ScrollDown(n),
+                0x00D0..=0x00DF => Instruction::ScrollUp(n),
+                0x00FB => Instruction::ScrollRight,
+                0x00FC => Instruction::ScrollLeft,
+                0x00FD => Instruction::Exit,
+                0x00FE => Instruction::LowRes,
+                0x00FF => Instruction::HighRes,
+                _ => Instruction::Sys(address),
+            },
+            0x1 => Instruction::Jump(address),
+            0x2 => Instruction::Call(address),
+            0x3 => Instruction::SkipEqByte { x, byte },
+            0x4 => Instruction::SkipNeByte { x, byte },
+            0x5 => match n {
+                0x0 => Instruction::SkipEqReg { x, y },
+                0x2 => Instruction::StoreRange { x, y },
+                0x3 => Instruction::LoadRange { x, y },
+                _ => return None,
+            },
+            0x6 => Instruction::LoadByte { x, byte },
+            0x7 => Instruction::AddByte { x, byte },
+            0x8 => match n {
+                0x0 => Instruction::LoadReg { x, y },
+                0x1 => Instruction::Or { x, y },
+                0x2 => Instruction::And { x, y },
+                0x3 => Instruction::Xor { x, y },
+                0x4 => Instruction::AddReg { x, y },
+                0x5 => Instruction::Sub { x, y },
+                0x6 => Instruction::ShiftRight { x, y },
+                0x7 => Instruction::SubN { x, y },
+                0xE => Instruction::ShiftLeft { x, y },
+                _ => return None,
+            },
+            0x9 if n == 0 => Instruction::SkipNeReg { x, y },
+            0xA => Instruction::LoadI(address),
+            0xB => Instruction::JumpOffset(address),
+            0xC => Instruction::Random { x, byte },
+            0xD => Instruction::Draw { x, y, n },
+            0xE => match byte {
+                0x9E => Instruction::SkipKey { x },
+                0xA1 => Instruction::SkipNotKey { x },
+                _ => return None,
+            },
+            0xF => match byte {
+                0x01 => Instruction::SelectPlanes(x),
+                0x02 if x == 0 => Instruction::LoadAudio,
+                0x07 => Instruction::LoadDelay { x },
+                0x0A => Instruction::WaitKey { x },
+                0x15 => Instruction::SetDelay { x },
+                0x18 => Instruction::SetSound { x },
+                0x1E => Instruction::AddI { x },
+                0x29 => Instruction::LoadFont { x },
+                0x30 => Instruction::LoadBigFont { x },
+                0x33 => Instruction::StoreBcd { x },
+                0x3A => Instruction::SetPitch { x },
+                0x55 => Instruction::StoreRegs { x },
+                0x65 => Instruction::LoadRegs { x },
+                0x75 => Instruction::StoreFlags { x },
+                0x85 => Instruction::LoadFlags { x },
+                _ => return None,
+            },
+            _ => return None,
+        };
+        Some(instruction)
+    }
+
+    /// Decodes the four byte instruction made of `opcode` and the word after it. Only
+    /// `F000 NNNN` has this form.
+    pub fn decode_long(opcode: u16, operand: u16) -> Option<Self> {
+        (opcode == LONG_LOAD_I).then_some(Instruction::LoadLongI(operand))
+    }
+
+    /// Size of the encoded instruction in bytes.
+    pub fn size(self) -> u16 {
+        match self {
+            Instruction::LoadLongI(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Encodes the instruction back into its instruction word. Out of range operands
+    /// are truncated to the bits available. For `F000 NNNN`, this is the first word
+    /// only.
+    pub fn encode(self) -> u16 {
+        let xnn = |prefix: u16, x: u8, byte: u8| prefix << 12 | (x as u16 & 0xF) << 8 | byte as u16;
+        let xyn = |prefix: u16, x: u8, y: u8, n: u8| {
+            prefix << 12 | (x as u16 & 0xF) << 8 | (y as u16 & 0xF) << 4 | (n as u16 & 0xF)
+        };
+        let fx = |x: u8, byte: u8| xnn(0xF, x, byte);
+        match self {
+            Instruction::Sys(address) => address & 0x0FFF,
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            Instruction::ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::Jump(address) => 0x1000 | address & 0x0FFF,
+            Instruction::Call(address) => 0x2000 | address & 0x0FFF,
+            Instruction::SkipEqByte { x, byte } => xnn(0x3, x, byte),
+            Instruction::SkipNeByte { x, byte } => xnn(0x4, x, byte),
+            Instruction::SkipEqReg { x, y } => xyn(0x5, x, y, 0x0),
+            Instruction::StoreRange { x, y } => xyn(0x5, x, y, 0x2),
+            Instruction::LoadRange { x, y } => xyn(0x5, x, y, 0x3),
+            Instruction::LoadByte { x, byte } => xnn(0x6, x, byte),
+            Instruction::AddByte { x, byte } => xnn(0x7, x, byte),
+            Instruction::LoadReg { x, y } => xyn(0x8, x, y, 0x0),
+            Instruction::Or { x, y } => xyn(0x8, x, y, 0x1),
+            Instruction::And { x, y } => xyn(0x8, x, y, 0x2),
+            Instruction::Xor { x, y } => xyn(0x8, x, y, 0x3),
+            Instruction::AddReg { x, y } => xyn(0x8, x, y, 0x4),
+            Instruction::Sub { x, y } => xyn(0x8, x, y, 0x5),
+            Instruction::ShiftRight { x, y } => xyn(0x8, x, y, 0x6),
+            Instruction::SubN { x, y } => xyn(0x8, x, y, 0x7),
+            Instruction::ShiftLeft { x, y } => xyn(0x8, x, y, 0xE),
+            Instruction::SkipNeReg { x, y } => xyn(0x9, x, y, 0x0),
+            Instruction::LoadI(address) => 0xA000 | address & 0x0FFF,
+            Instruction::JumpOffset(address) => 0xB000 | address & 0x0FFF,
+            Instruction::Random { x, byte } => xnn(0xC, x, byte),
+            Instruction::Draw { x, y, n } => xyn(0xD, x, y, n),
+            Instruction::SkipKey { x } => xnn(0xE, x, 0x9E),
+            Instruction::SkipNotKey { x } => xnn(0xE, x, 0xA1),
+            Instruction::LoadLongI(_) => LONG_LOAD_I,
+            Instruction::SelectPlanes(n) => fx(n, 0x01),
+            Instruction::LoadAudio => 0xF002,
+            Instruction::LoadDelay { x } => fx(x, 0x07),
+            Instruction::WaitKey { x } => fx(x, 0x0A),
+            Instruction::SetDelay { x } => fx(x, 0x15),
+            Instruction::SetSound { x } => fx(x, 0x18),
+            Instruction::AddI { x } => fx(x, 0x1E),
+            Instruction::LoadFont { x } => fx(x, 0x29),
+            Instruction::LoadBigFont { x } => fx(x, 0x30),
+            Instruction::StoreBcd { x } => fx(x, 0x33),
+            Instruction::SetPitch { x } => fx(x, 0x3A),
+            Instruction::StoreRegs { x } => fx(x, 0x55),
+            Instruction::LoadRegs { x } => fx(x, 0x65),
+            Instruction::StoreFlags { x } => fx(x, 0x75),
+            Instruction::LoadFlags { x } => fx(x, 0x85),
+        }
+    }
+}
+
+/// Formats the instruction in the classic CHIP-8 assembly syntax, e.g. `LD V3, 0x10`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Instruction::Sys(address) => write!(f, "SYS {:#05x}", address),
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollDown(n) => write!(f, "SCD {}", n),
+            Instruction::ScrollUp(n) => write!(f, "SCU {}", n),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::Jump(address) => write!(f, "JP {:#05x}", address),
+            Instruction::Call(address) => write!(f, "CALL {:#05x}", address),
+            Instruction::SkipEqByte { x, byte } => write!(f, "SE V{:X}, {:#04x}", x, byte),
+            Instruction::SkipNeByte { x, byte } => write!(f, "SNE V{:X}, {:#04x}", x, byte),
+            Instruction::SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::StoreRange { x, y } => write!(f, "LD [I], V{:X}-V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LD V{:X}-V{:X}, [I]", x, y),
+            Instruction::LoadByte { x, byte } => write!(f, "LD V{:X}, {:#04x}", x, byte),
+            Instruction::AddByte { x, byte } => write!(f, "ADD V{:X}, {:#04x}", x, byte),
+            Instruction::LoadReg { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubN { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadI(address) => write!(f, "LD I, {:#05x}", address),
+            Instruction::JumpOffset(address) => write!(f, "JP V0, {:#05x}", address),
+            Instruction::Random { x, byte } => write!(f, "RND V{:X}, {:#04x}", x, byte),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKey { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipNotKey { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::LoadLongI(address) => write!(f, "LD I, {:#06x}", address),
+            Instruction::SelectPlanes(n) => write!(f, "PLANE {}", n),
+            Instruction::LoadAudio => write!(f, "AUDIO"),
+            Instruction::LoadDelay { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddI { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::LoadFont { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::LoadBigFont { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::StoreBcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::SetPitch { x } => write!(f, "LD PITCH, V{:X}", x),
+            Instruction::StoreRegs { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::LoadRegs { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::StoreFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+        }
+    }
+}
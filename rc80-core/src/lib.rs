@@ -1,295 +1,920 @@
+use std::fmt;
+use std::ops::Range;
+
+pub mod debugger;
+pub mod disasm;
+pub mod display;
+pub mod error;
+pub mod expr;
+pub mod font;
+pub mod instruction;
+pub mod movie;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod state;
+pub mod symbols;
+#[cfg(test)]
+mod tests;
+pub mod trace;
+pub mod variant;
+
+pub use debugger::{Breakpoint, BreakpointId, Debugger, StopReason};
+pub use display::Display;
+pub use error::{BreakpointError, EmuError, ExprError, MovieError, StateError};
+pub use expr::Expr;
+pub use font::Font;
+use font::{
+    BIG_FONT, BIG_FONT_CHAR_SIZE, BIG_FONT_SIZE, DEFAULT_FONT_ADDRESS, FONT_CHAR_SIZE, FONT_SIZE,
+    MAX_FONT_ADDRESS,
+};
+pub use instruction::Instruction;
+use instruction::LONG_LOAD_I;
+pub use movie::{Movie, MovieFrame};
+pub use quirks::Quirks;
+pub use rewind::Rewind;
+pub use rng::{Rng, XorShiftRng};
+pub use symbols::SymbolMap;
+use trace::RegisterSnapshot;
+pub use trace::{TraceEvent, Tracer};
+pub use variant::Variant;
+
 pub const MEMORY_SIZE: usize = 4096;
+/// Memory available to XO-CHIP programs, the whole 16 bit address space.
+pub const XO_MEMORY_SIZE: usize = 65536;
+pub const PROGRAM_START: usize = 0x200;
 pub const REGISTER_AMOUNT: usize = 16;
 pub const STACK_SIZE: usize = 16;
+pub const KEY_AMOUNT: usize = 16;
+/// Number of SUPER-CHIP RPL user flags available to FX75/FX85.
+pub const FLAG_AMOUNT: usize = 16;
+/// Rate at which the delay and sound timers count down, in Hz.
+pub const TIMER_FREQUENCY: u32 = 60;
+pub const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 11;
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
-pub const PIXEL_AMOUNT: usize = SCREEN_WIDTH * SCREEN_HEIGHT;
+/// Dimensions of the SUPER-CHIP high resolution mode.
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
+/// Number of XO-CHIP bitplanes. Other variants only draw to the first one.
+pub const PLANE_AMOUNT: usize = 2;
+/// Size of the XO-CHIP audio pattern buffer, 128 one-bit samples.
+pub const AUDIO_PATTERN_SIZE: usize = 16;
+/// Pitch at which the audio pattern plays back at 4000 samples per second.
+pub const DEFAULT_PITCH: u8 = 64;
 
-pub type ScreenPixels = [u8; PIXEL_AMOUNT];
+/// Which keypad event completes an FX0A "wait for key" instruction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum KeyWaitTrigger {
+    /// Resume as soon as a key goes down.
+    #[default]
+    Press,
+    /// Resume once the pressed key is let go again, like the COSMAC VIP did.
+    Release,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct KeyWait {
+    register: usize,
+    pressed: Option<u8>,
+}
 
+/// With the `serde` feature, the random number generator is serialized as its snapshot
+/// and always comes back as an `XorShiftRng`, and the tracer is skipped. Deserializing
+/// rejects machines that save states would reject too.
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(try_from = "SystemRepr")
+)]
 pub struct System {
     pub program_counter: u16,
     pub stack_pointer: u8,
     pub stack: [u16; STACK_SIZE],
-    pub memory: [u8; MEMORY_SIZE],
+    /// Sized for the current variant, see [`Variant::memory_size`].
+    pub memory: Vec<u8>,
     pub v_registers: [u8; REGISTER_AMOUNT],
     pub i_register: u16,
     pub time_register: u8,
     pub sound_register: u8,
-    pub pixels: ScreenPixels,
+    /// SUPER-CHIP RPL user flags.
+    pub flags: [u8; FLAG_AMOUNT],
+    /// XO-CHIP audio samples, played most significant bit first while the sound timer
+    /// runs.
+    pub audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    /// XO-CHIP audio playback pitch, see [`System::audio_sample_rate`].
+    pub pitch: u8,
+    pub variant: Variant,
+    pub key_wait_trigger: KeyWaitTrigger,
+    pub quirks: Quirks,
+    /// Number of instructions `run_frame` executes before ticking the timers.
+    pub instructions_per_frame: u32,
+    keys: [bool; KEY_AMOUNT],
+    key_wait: Option<KeyWait>,
+    vblank_wait: bool,
+    display: Display,
+    halted: bool,
+    planes: u8,
+    font: Font,
+    font_address: u16,
+    #[cfg_attr(feature = "serde", serde(with = "rng::serde_state"))]
+    rng: Box<dyn Rng>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    tracer: Option<Box<dyn Tracer>>,
+}
+
+/// Clones the whole machine, including the random number generator state. The clone
+/// has no tracer. A generator whose `Rng::clone_rng` returns `None` is replaced in the
+/// clone by an `XorShiftRng` restored from its snapshot, so the clone's random numbers
+/// may differ.
+impl Clone for System {
+    fn clone(&self) -> Self {
+        Self {
+            program_counter: self.program_counter,
+            stack_pointer: self.stack_pointer,
+            stack: self.stack,
+            memory: self.memory.clone(),
+            v_registers: self.v_registers,
+            i_register: self.i_register,
+            time_register: self.time_register,
+            sound_register: self.sound_register,
+            flags: self.flags,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            variant: self.variant,
+            key_wait_trigger: self.key_wait_trigger,
+            quirks: self.quirks,
+            instructions_per_frame: self.instructions_per_frame,
+            keys: self.keys,
+            key_wait: self.key_wait,
+            vblank_wait: self.vblank_wait,
+            display: self.display.clone(),
+            halted: self.halted,
+            planes: self.planes,
+            font: self.font,
+            font_address: self.font_address,
+            rng: self
+                .rng
+                .clone_rng()
+                .unwrap_or_else(|| rng::replacement(self.rng.snapshot())),
+            tracer: None,
+        }
+    }
+}
+
+/// Deserialized form of a system, checked before it becomes one.
+#[cfg(feature = "serde")]
+#[derive(serde::Deserialize)]
+#[serde(rename = "System")]
+struct SystemRepr {
+    program_counter: u16,
+    stack_pointer: u8,
+    stack: [u16; STACK_SIZE],
+    memory: Vec<u8>,
+    v_registers: [u8; REGISTER_AMOUNT],
+    i_register: u16,
+    time_register: u8,
+    sound_register: u8,
+    flags: [u8; FLAG_AMOUNT],
+    audio_pattern: [u8; AUDIO_PATTERN_SIZE],
+    pitch: u8,
+    variant: Variant,
+    key_wait_trigger: KeyWaitTrigger,
+    quirks: Quirks,
+    instructions_per_frame: u32,
+    keys: [bool; KEY_AMOUNT],
+    key_wait: Option<KeyWait>,
+    vblank_wait: bool,
+    display: Display,
+    halted: bool,
+    planes: u8,
+    font: Font,
+    font_address: u16,
+    #[serde(with = "rng::serde_state")]
+    rng: Box<dyn Rng>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<SystemRepr> for System {
+    type Error = StateError;
+
+    fn try_from(repr: SystemRepr) -> Result<Self, Self::Error> {
+        let sys = System {
+            program_counter: repr.program_counter,
+            stack_pointer: repr.stack_pointer,
+            stack: repr.stack,
+            memory: repr.memory,
+            v_registers: repr.v_registers,
+            i_register: repr.i_register,
+            time_register: repr.time_register,
+            sound_register: repr.sound_register,
+            flags: repr.flags,
+            audio_pattern: repr.audio_pattern,
+            pitch: repr.pitch,
+            variant: repr.variant,
+            key_wait_trigger: repr.key_wait_trigger,
+            quirks: repr.quirks,
+            instructions_per_frame: repr.instructions_per_frame,
+            keys: repr.keys,
+            key_wait: repr.key_wait,
+            vblank_wait: repr.vblank_wait,
+            display: repr.display,
+            halted: repr.halted,
+            planes: repr.planes,
+            font: repr.font,
+            font_address: repr.font_address,
+            rng: repr.rng,
+            tracer: None,
+        };
+        sys.check_invariants()?;
+        Ok(sys)
+    }
+}
+
+impl fmt::Debug for System {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("System")
+            .field("program_counter", &self.program_counter)
+            .field("stack_pointer", &self.stack_pointer)
+            .field("stack", &self.stack)
+            .field("memory", &self.memory)
+            .field("v_registers", &self.v_registers)
+            .field("i_register", &self.i_register)
+            .field("time_register", &self.time_register)
+            .field("sound_register", &self.sound_register)
+            .field("flags", &self.flags)
+            .field("audio_pattern", &self.audio_pattern)
+            .field("pitch", &self.pitch)
+            .field("variant", &self.variant)
+            .field("key_wait_trigger", &self.key_wait_trigger)
+            .field("quirks", &self.quirks)
+            .field("instructions_per_frame", &self.instructions_per_frame)
+            .field("keys", &self.keys)
+            .field("key_wait", &self.key_wait)
+            .field("vblank_wait", &self.vblank_wait)
+            .field("display", &self.display)
+            .field("halted", &self.halted)
+            .field("planes", &self.planes)
+            .field("font", &self.font)
+            .field("font_address", &self.font_address)
+            .field("rng_state", &self.rng.snapshot())
+            .field("has_tracer", &self.tracer.is_some())
+            .finish()
+    }
 }
 
 impl Default for System {
     fn default() -> Self {
-        Self {
+        let mut sys = Self {
             program_counter: 0x200,
             stack_pointer: 0,
             stack: [0; STACK_SIZE],
-            memory: [0; MEMORY_SIZE],
+            memory: vec![0; MEMORY_SIZE],
             v_registers: [0; REGISTER_AMOUNT],
             i_register: 0,
             time_register: 0,
             sound_register: 0,
-            pixels: [0; PIXEL_AMOUNT],
-        }
+            flags: [0; FLAG_AMOUNT],
+            audio_pattern: [0; AUDIO_PATTERN_SIZE],
+            pitch: DEFAULT_PITCH,
+            variant: Variant::default(),
+            key_wait_trigger: KeyWaitTrigger::default(),
+            quirks: Quirks::default(),
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            keys: [false; KEY_AMOUNT],
+            key_wait: None,
+            vblank_wait: false,
+            display: Display::new(SCREEN_WIDTH, SCREEN_HEIGHT, PLANE_AMOUNT),
+            halted: false,
+            planes: 1,
+            font: Font::default(),
+            font_address: DEFAULT_FONT_ADDRESS,
+            rng: Box::new(XorShiftRng::default()),
+            tracer: None,
+        };
+        sys.write_font(Font::default(), DEFAULT_FONT_ADDRESS);
+        sys
     }
 }
 
 impl System {
-    pub fn load(&mut self, program_bytes: &[u8]) {
-        println!("Memory length: {}", self.memory.len());
-        println!("Program length: {}", program_bytes.len());
-        let len = program_bytes.len() + 0x200;
-        println!("Calculated destination length: {}", len);
-        self.memory[0x200..len].copy_from_slice(program_bytes);
-    }
-
-    pub fn step(&mut self) {
-        let opcode = (self.memory[self.program_counter as usize] as u16) << 8
-            | self.memory[self.program_counter as usize + 1] as u16;
-        println!("Found opcode: {:#06x}", opcode);
-        let code_type = opcode >> 12;
-        let mut jumped = false;
-        match code_type {
-            0x0 => match opcode {
-                0x00E0 => {
-                    println!("Clear screen!");
-                    self.clear_screen();
+    /// Creates a system for `variant`, using its reference quirks and font.
+    pub fn with_variant(variant: Variant) -> Self {
+        let mut sys = Self::default();
+        sys.set_variant(variant);
+        sys
+    }
+
+    /// Switches to `variant`, along with its reference quirks and font. Memory is
+    /// resized to fit the variant, truncating anything past its end.
+    pub fn set_variant(&mut self, variant: Variant) {
+        self.variant = variant;
+        self.memory.resize(variant.memory_size(), 0);
+        self.quirks = variant.quirks();
+        self.write_font(variant.font(), self.font_address);
+    }
+
+    /// Writes `font` into the reserved interpreter area at `address`, replacing the
+    /// previously loaded font. The SUPER-CHIP big font is written right after it, and
+    /// both must fit below 0x200, so `address` can be at most [`MAX_FONT_ADDRESS`].
+    pub fn set_font(&mut self, font: Font, address: u16) -> Result<(), EmuError> {
+        if address > MAX_FONT_ADDRESS {
+            return Err(EmuError::FontOutOfRange { address });
+        }
+        self.write_font(font, address);
+        Ok(())
+    }
+
+    /// `set_font` for addresses known to fit.
+    fn write_font(&mut self, font: Font, address: u16) {
+        let address = address as usize;
+        let size = FONT_SIZE + BIG_FONT_SIZE;
+        let old_address = self.font_address as usize;
+        self.memory[old_address..old_address + size].fill(0);
+        self.memory[address..address + FONT_SIZE].copy_from_slice(font.glyphs());
+        self.memory[address + FONT_SIZE..address + size].copy_from_slice(&BIG_FONT);
+        self.font = font;
+        self.font_address = address as u16;
+    }
+
+    pub fn font(&self) -> Font {
+        self.font
+    }
+
+    pub fn font_address(&self) -> u16 {
+        self.font_address
+    }
+
+    /// Creates a system whose CXNN instruction draws from a generator seeded with `seed`.
+    pub fn with_seed(seed: u64) -> Self {
+        let mut sys = Self::default();
+        sys.set_rng(Box::new(XorShiftRng::new(seed)));
+        sys
+    }
+
+    /// Replaces the random number source used by CXNN.
+    pub fn set_rng(&mut self, rng: Box<dyn Rng>) {
+        self.rng = rng;
+    }
+
+    /// Installs a tracer that gets notified of every executed instruction. The system is
+    /// silent when no tracer is set.
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer>) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Removes and returns the current tracer.
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.take()
+    }
+
+    /// Marks hex key `key` (0x0-0xF) as held down. Other keys are ignored.
+    pub fn press_key(&mut self, key: u8) {
+        match self.keys.get_mut(key as usize) {
+            Some(held @ false) => *held = true,
+            _ => return,
+        }
+        if let Some(wait) = &mut self.key_wait {
+            match self.key_wait_trigger {
+                KeyWaitTrigger::Press => {
+                    self.v_registers[wait.register] = key;
+                    self.key_wait = None;
                 }
-                0x00EE => {
-                    println!("Return from subroutine");
-                    self.stack_pointer -= 1;
-                    self.program_counter = self.stack[self.stack_pointer as usize];
-                    self.stack[self.stack_pointer as usize] = 0;
+                KeyWaitTrigger::Release => {
+                    if wait.pressed.is_none() {
+                        wait.pressed = Some(key);
+                    }
                 }
-                _ => {
-                    println!("not supported");
+            }
+        }
+    }
+
+    /// Marks hex key `key` (0x0-0xF) as released. Other keys are ignored.
+    pub fn release_key(&mut self, key: u8) {
+        let Some(held) = self.keys.get_mut(key as usize) else {
+            return;
+        };
+        *held = false;
+        if let Some(KeyWait {
+            register,
+            pressed: Some(pressed),
+        }) = self.key_wait
+        {
+            if pressed == key {
+                self.v_registers[register] = key;
+                self.key_wait = None;
+            }
+        }
+    }
+
+    /// Whether hex key `key` is held down, which keys past 0xF never are.
+    pub fn is_key_pressed(&self, key: u8) -> bool {
+        self.keys.get(key as usize).copied().unwrap_or(false)
+    }
+
+    /// Returns the held keys as a bitmask, bit N set for key N.
+    pub fn pressed_keys(&self) -> u16 {
+        (0..KEY_AMOUNT as u8)
+            .filter(|key| self.is_key_pressed(*key))
+            .fold(0, |keys, key| keys | 1 << key)
+    }
+
+    /// Presses and releases keys so that exactly the keys in the bitmask `keys` are held.
+    pub fn set_pressed_keys(&mut self, keys: u16) {
+        for key in 0..KEY_AMOUNT as u8 {
+            let down = keys >> key & 1 == 1;
+            if down && !self.is_key_pressed(key) {
+                self.press_key(key);
+            } else if !down && self.is_key_pressed(key) {
+                self.release_key(key);
+            }
+        }
+    }
+
+    /// Whether execution is blocked on an FX0A instruction.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.key_wait.is_some()
+    }
+
+    /// Whether the program stopped itself with 00FD.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Whether the SUPER-CHIP 128x64 mode is active.
+    pub fn is_hires(&self) -> bool {
+        self.display.width() == HIRES_SCREEN_WIDTH
+    }
+
+    /// The screen, at the resolution of the current display mode.
+    pub fn display(&self) -> &Display {
+        &self.display
+    }
+
+    /// Bitmask of the XO-CHIP planes that drawing, clearing and scrolling affect.
+    pub fn selected_planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// Playback rate of the XO-CHIP audio pattern in samples per second.
+    pub fn audio_sample_rate(&self) -> f64 {
+        4000. * 2f64.powf((self.pitch as f64 - 64.) / 48.)
+    }
+
+    /// Whether execution is paused until the next frame by the display wait quirk.
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.vblank_wait
+    }
+
+    pub fn load(&mut self, program_bytes: &[u8]) -> Result<(), EmuError> {
+        let max = self.memory.len() - PROGRAM_START;
+        if program_bytes.len() > max {
+            return Err(EmuError::RomTooLarge {
+                size: program_bytes.len(),
+                max,
+            });
+        }
+        let len = program_bytes.len() + PROGRAM_START;
+        self.memory[PROGRAM_START..len].copy_from_slice(program_bytes);
+        if let Some(tracer) = &mut self.tracer {
+            tracer.on_load(program_bytes.len());
+        }
+        Ok(())
+    }
+
+    /// Runs one 60 Hz frame: `instructions_per_frame` instructions followed by a
+    /// timer tick.
+    pub fn run_frame(&mut self) -> Result<(), EmuError> {
+        for _ in 0..self.instructions_per_frame {
+            self.step()?;
+        }
+        self.tick_timers();
+        Ok(())
+    }
+
+    /// Decrements the delay and sound timers. Meant to be called at `TIMER_FREQUENCY`.
+    pub fn tick_timers(&mut self) {
+        self.time_register = self.time_register.saturating_sub(1);
+        self.sound_register = self.sound_register.saturating_sub(1);
+        self.vblank_wait = false;
+    }
+
+    /// Whether the buzzer should currently be sounding.
+    pub fn is_sound_playing(&self) -> bool {
+        self.sound_register > 0
+    }
+
+    /// Executes a single instruction. On error, the instruction has no effect and the
+    /// program counter still points at it.
+    pub fn step(&mut self) -> Result<(), EmuError> {
+        if self.key_wait.is_some() || self.vblank_wait || self.halted {
+            return Ok(());
+        }
+        let address = self.program_counter;
+        let opcode = self.fetch()?;
+        // Only read the operand where F000 exists, so elsewhere it is invalid even at the
+        // end of memory
+        let instruction = match opcode {
+            LONG_LOAD_I if self.variant.supports(Instruction::LoadLongI(0)) => {
+                let operand = self.read_word(address.wrapping_add(2))?;
+                Instruction::decode_long(opcode, operand)
+            }
+            _ => Instruction::decode(opcode),
+        };
+        let instruction = instruction
+            .filter(|instruction| self.variant.supports(*instruction))
+            .ok_or(EmuError::InvalidOpcode { address, opcode })?;
+        if self.tracer.is_none() {
+            return self.execute(instruction);
+        }
+        let before = RegisterSnapshot::capture(self);
+        self.execute(instruction)?;
+        let event = TraceEvent {
+            address,
+            opcode,
+            instruction,
+            changes: before.changes(&RegisterSnapshot::capture(self)),
+        };
+        if let Some(tracer) = &mut self.tracer {
+            tracer.on_step(&event);
+        }
+        Ok(())
+    }
+
+    /// Reads the instruction word at the program counter.
+    pub fn fetch(&self) -> Result<u16, EmuError> {
+        self.read_word(self.program_counter)
+    }
+
+    fn read_word(&self, address: u16) -> Result<u16, EmuError> {
+        let range = self.memory_range(address as usize, 2)?;
+        Ok((self.memory[range.start] as u16) << 8 | self.memory[range.start + 1] as u16)
+    }
+
+    /// Moves the program counter past the instruction after the current one, which on
+    /// XO-CHIP may be four bytes long.
+    fn skip(&mut self) -> Result<(), EmuError> {
+        let next = self.program_counter + 2;
+        let size: u16 = match self.read_word(next) {
+            Ok(LONG_LOAD_I) if self.variant == Variant::XoChip => 4,
+            _ => 2,
+        };
+        self.check_program_counter(next as usize + size as usize)?;
+        self.program_counter = next + size - 2;
+        Ok(())
+    }
+
+    /// Fails if the program counter can't move to `address`, past the end of memory.
+    fn check_program_counter(&self, address: usize) -> Result<(), EmuError> {
+        if address >= self.memory.len() {
+            return Err(EmuError::OutOfBounds { address });
+        }
+        Ok(())
+    }
+
+    /// Runs a decoded instruction as if it had been fetched from the program counter.
+    pub fn execute(&mut self, instruction: Instruction) -> Result<(), EmuError> {
+        let address = self.program_counter;
+        // The program counter never leaves memory, so an instruction that would move it
+        // past the end fails up front, before it has any effect
+        let falls_through = !matches!(
+            instruction,
+            Instruction::Jump(_)
+                | Instruction::Call(_)
+                | Instruction::JumpOffset(_)
+                | Instruction::Return
+                | Instruction::Exit
+        );
+        if falls_through {
+            self.check_program_counter(address as usize + instruction.size() as usize)?;
+        }
+        let mut jumped = false;
+        match instruction {
+            Instruction::Sys(_) => {
+                // 0NNN calls native machine code, which there is none of here
+            }
+            Instruction::ClearScreen => {
+                self.display.clear(self.planes);
+            }
+            Instruction::Return => {
+                if self.stack_pointer == 0 {
+                    return Err(EmuError::StackUnderflow { address });
                 }
-            },
-            0x1 => {
-                let target = opcode & 0x0FFF;
-                println!("Jump to {:#x}", target);
+                // The stack holds the call, which is followed by the return address
+                let call = self.stack[self.stack_pointer as usize - 1];
+                self.check_program_counter(call as usize + 2)?;
+                self.stack_pointer -= 1;
+                self.program_counter = self.stack[self.stack_pointer as usize];
+                self.stack[self.stack_pointer as usize] = 0;
+            }
+            Instruction::ScrollDown(n) => {
+                self.display.scroll(0, n as isize, self.planes);
+            }
+            Instruction::ScrollUp(n) => {
+                self.display.scroll(0, -(n as isize), self.planes);
+            }
+            Instruction::ScrollRight => {
+                self.display.scroll(4, 0, self.planes);
+            }
+            Instruction::ScrollLeft => {
+                self.display.scroll(-4, 0, self.planes);
+            }
+            Instruction::Exit => {
+                self.halted = true;
+                jumped = true;
+            }
+            Instruction::LowRes => {
+                self.display.resize(SCREEN_WIDTH, SCREEN_HEIGHT);
+            }
+            Instruction::HighRes => {
+                self.display.resize(HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT);
+            }
+            Instruction::Jump(target) => {
                 self.program_counter = target;
                 jumped = true;
             }
-            0x2 => {
-                let target = opcode & 0x0FFF;
-                println!("Call subroutine {:x}", target);
+            Instruction::Call(target) => {
+                if self.stack_pointer as usize >= STACK_SIZE {
+                    return Err(EmuError::StackOverflow { address });
+                }
                 self.stack[self.stack_pointer as usize] = self.program_counter;
                 self.stack_pointer += 1;
                 self.program_counter = target;
                 jumped = true;
             }
-            0x3 => {
-                let register = (opcode >> 8 & 0xF) as usize;
-                let value = (opcode & 0xFF) as u8;
-                println!("Register V{:x} == {}?", register, value);
-                if self.v_registers[register] == value {
-                    println!("Skipped line");
-                    self.program_counter += 2;
+            Instruction::SkipEqByte { x, byte } => {
+                if self.v_registers[x as usize] == byte {
+                    self.skip()?;
                 }
             }
-            0x4 => {
-                let register = (opcode >> 8 & 0xF) as usize;
-                let value = (opcode & 0xFF) as u8;
-                println!("Register V{:x} != {}?", register, value);
-                if self.v_registers[register] != value {
-                    println!("Skipped line");
-                    self.program_counter += 2;
+            Instruction::SkipNeByte { x, byte } => {
+                if self.v_registers[x as usize] != byte {
+                    self.skip()?;
                 }
             }
-            0x5 => {
-                let register1 = (opcode >> 8 & 0xF) as usize;
-                let register2 = (opcode >> 4 & 0xF) as usize;
-                println!("Register V{:x} == V{:x}?", register1, register2);
-                if self.v_registers[register1] == self.v_registers[register2] {
-                    println!("Skipped line");
-                    self.program_counter += 2;
+            Instruction::SkipEqReg { x, y } => {
+                if self.v_registers[x as usize] == self.v_registers[y as usize] {
+                    self.skip()?;
                 }
             }
-            0x6 => {
-                let register_index = (opcode >> 8 & 0xF) as usize;
-                let register_value = (opcode & 0xFF) as u8;
-                println!("Set register V{:x} to {}", register_index, register_value);
-                self.v_registers[register_index] = register_value;
-            }
-            0x7 => {
-                let register_index = (opcode >> 8 & 0xF) as usize;
-                let value = (opcode & 0xFF) as u8;
-                println!("Increment register V{:x} by {}", register_index, value);
-                let reg = &mut self.v_registers[register_index];
-                *reg = reg.overflowing_add(value).0;
-            }
-            0x8 => {
-                let register1 = (opcode >> 8 & 0xF) as usize;
-                let register2 = (opcode >> 4 & 0xF) as usize;
-                let op = opcode & 0xF;
-                let reg2 = self.v_registers[register2];
-                let reg1 = &mut self.v_registers[register1];
-                match op {
-                    0x0 => {
-                        println!("Operation V{:x} = V{:x}", register1, register2);
-                        *reg1 = reg2;
-                    }
-                    0x1 => {
-                        println!("Operation V{:x} |= V{:x}", register1, register2);
-                        *reg1 |= reg2;
-                    }
-                    0x2 => {
-                        println!("Operation V{:x} &= V{:x}", register1, register2);
-                        *reg1 &= reg2;
-                    }
-                    0x3 => {
-                        println!("Operation V{:x} ^= V{:x}", register1, register2);
-                        *reg1 ^= reg2;
-                    }
-                    0x4 => {
-                        println!("Operation V{:x} += V{:x}", register1, register2);
-                        let res = reg1.overflowing_add(reg2);
-                        *reg1 = res.0;
-                        self.v_registers[15] = res.1 as u8;
-                    }
-                    0x5 => {
-                        println!("Operation V{:x} -= V{:x}", register1, register2);
-                        let res = reg1.overflowing_sub(reg2);
-                        *reg1 = res.0;
-                        self.v_registers[15] = res.1 as u8;
-                    }
-                    0x6 => {
-                        println!("Operation V{:x} >> 1", register1);
-                        let res = reg1.overflowing_shr(1);
-                        *reg1 = res.0;
-                        self.v_registers[15] = res.1 as u8;
-                    }
-                    0x7 => {
-                        println!(
-                            "Operation V{:x} = V{:x} - V{:x}",
-                            register1, register2, register1
-                        );
-                        let res = reg2.overflowing_sub(*reg1);
-                        *reg1 = res.0;
-                        self.v_registers[15] = res.1 as u8;
-                    }
-                    0xE => {
-                        println!("Operation V{:x} << 1", register1);
-                        let res = reg1.overflowing_shl(1);
-                        *reg1 = res.0;
-                        self.v_registers[15] = res.1 as u8;
-                    }
-                    _ => {
-                        println!("what the hell??");
-                    }
+            Instruction::StoreRange { x, y } => {
+                let (low, high) = (x.min(y) as usize, x.max(y) as usize);
+                let range = self.memory_range(self.i_register as usize, high - low + 1)?;
+                self.memory[range].copy_from_slice(&self.v_registers[low..=high]);
+                if x > y {
+                    self.memory[self.i_register as usize..][..high - low + 1].reverse();
                 }
             }
-            0x9 => {
-                let register1 = (opcode >> 8 & 0xF) as usize;
-                let register2 = (opcode >> 4 & 0xF) as usize;
-                println!("Register V{:x} != V{:x}?", register1, register2);
-                if self.v_registers[register1] != self.v_registers[register2] {
-                    println!("Skipped line");
-                    self.program_counter += 2;
+            Instruction::LoadRange { x, y } => {
+                let (low, high) = (x.min(y) as usize, x.max(y) as usize);
+                let range = self.memory_range(self.i_register as usize, high - low + 1)?;
+                self.v_registers[low..=high].copy_from_slice(&self.memory[range]);
+                if x > y {
+                    self.v_registers[low..=high].reverse();
                 }
             }
-            0xA => {
-                let register_value = opcode & 0x0FFF;
-                println!("Set register I to {:#x}", register_value);
-                self.i_register = register_value;
-            }
-            0xD => {
-                let vx_reg = opcode >> 8 & 0xF;
-                let vy_reg = opcode >> 4 & 0xF;
-                let x = self.v_registers[vx_reg as usize];
-                let y = self.v_registers[vy_reg as usize];
-                let data_size = (opcode & 0xF) as usize;
-                println!("Draw sprite at {:?} with size {}", (x, y), data_size);
-                let mut bytes = vec![];
-                for i in 0..data_size {
-                    bytes.push(self.memory[self.i_register as usize + i]);
+            Instruction::LoadByte { x, byte } => {
+                self.v_registers[x as usize] = byte;
+            }
+            Instruction::AddByte { x, byte } => {
+                let reg = &mut self.v_registers[x as usize];
+                *reg = reg.wrapping_add(byte);
+            }
+            Instruction::LoadReg { x, y } => {
+                self.v_registers[x as usize] = self.v_registers[y as usize];
+            }
+            Instruction::Or { x, y } => {
+                self.v_registers[x as usize] |= self.v_registers[y as usize];
+                if self.quirks.logic_resets_vf {
+                    self.v_registers[15] = 0;
                 }
-                let mut pixel_data = vec![];
-                for byte in bytes {
-                    for b in (0..u8::BITS).rev() {
-                        let b = byte >> b & 1;
-                        pixel_data.push(b);
-                    }
+            }
+            Instruction::And { x, y } => {
+                self.v_registers[x as usize] &= self.v_registers[y as usize];
+                if self.quirks.logic_resets_vf {
+                    self.v_registers[15] = 0;
                 }
-                self.blit_sprite(x, y, &pixel_data);
-            }
-            0xF => {
-                let reg_val = opcode >> 8 & 0xF;
-                let code = opcode & 0xFF;
-                match code {
-                    0x1E => {
-                        let val = self.v_registers[reg_val as usize];
-                        println!("Incrementing I by V{:x} = {}", reg_val, val);
-                        self.i_register += val as u16;
-                    }
-                    0x33 => {
-                        println!("Saving BCD value of register V{:x}", reg_val);
-                        let val = format!("{:0>3}", self.v_registers[reg_val as usize]);
-                        println!("{:?}", val);
-                        val.as_bytes().iter().enumerate().for_each(|(i, v)| {
-                            println!("{} - {:b}", v, v);
-                            self.memory[self.i_register as usize + i] = v & 0xF;
-                        });
-                    }
-                    0x55 => {
-                        let reg_index = reg_val as usize;
-                        for i in 0..=reg_index {
-                            let val = self.v_registers[i];
-                            self.memory[self.i_register as usize] = val;
-                            println!(
-                                "Saving value of register V{:x} to address {:x}",
-                                i, self.i_register
-                            );
-                            self.i_register += 1;
-                        }
-                    }
-                    0x65 => {
-                        let reg_index = reg_val as usize;
-                        for i in 0..=reg_index {
-                            let val = self.memory[self.i_register as usize];
-                            self.v_registers[i] = val;
-                            self.i_register += 1;
-                            println!("Loading value for register V{:x} = {}", i, val);
-                        }
-                    }
-                    _ => {
-                        println!("unimplemented");
+            }
+            Instruction::Xor { x, y } => {
+                self.v_registers[x as usize] ^= self.v_registers[y as usize];
+                if self.quirks.logic_resets_vf {
+                    self.v_registers[15] = 0;
+                }
+            }
+            Instruction::AddReg { x, y } => {
+                let res =
+                    self.v_registers[x as usize].overflowing_add(self.v_registers[y as usize]);
+                self.v_registers[x as usize] = res.0;
+                self.v_registers[15] = res.1 as u8;
+            }
+            Instruction::Sub { x, y } => {
+                let res =
+                    self.v_registers[x as usize].overflowing_sub(self.v_registers[y as usize]);
+                self.v_registers[x as usize] = res.0;
+                // VF is set when there is no borrow
+                self.v_registers[15] = !res.1 as u8;
+            }
+            Instruction::ShiftRight { x, y } => {
+                let source = if self.quirks.shift_uses_vy { y } else { x };
+                let source = self.v_registers[source as usize];
+                self.v_registers[x as usize] = source >> 1;
+                self.v_registers[15] = source & 1;
+            }
+            Instruction::SubN { x, y } => {
+                let res =
+                    self.v_registers[y as usize].overflowing_sub(self.v_registers[x as usize]);
+                self.v_registers[x as usize] = res.0;
+                self.v_registers[15] = !res.1 as u8;
+            }
+            Instruction::ShiftLeft { x, y } => {
+                let source = if self.quirks.shift_uses_vy { y } else { x };
+                let source = self.v_registers[source as usize];
+                self.v_registers[x as usize] = source << 1;
+                self.v_registers[15] = source >> 7;
+            }
+            Instruction::SkipNeReg { x, y } => {
+                if self.v_registers[x as usize] != self.v_registers[y as usize] {
+                    self.skip()?;
+                }
+            }
+            Instruction::LoadI(target) => {
+                self.i_register = target;
+            }
+            Instruction::JumpOffset(target) => {
+                let offset_reg = if self.quirks.jump_uses_vx {
+                    (target >> 8) as usize
+                } else {
+                    0
+                };
+                let target = target as usize + self.v_registers[offset_reg] as usize;
+                self.check_program_counter(target)?;
+                self.program_counter = target as u16;
+                jumped = true;
+            }
+            Instruction::Random { x, byte } => {
+                self.v_registers[x as usize] = self.rng.next_u8() & byte;
+            }
+            Instruction::Draw { x, y, n } => {
+                let x = self.v_registers[x as usize];
+                let y = self.v_registers[y as usize];
+                let (width, height) = if n == 0 && self.variant != Variant::Chip8 {
+                    (16, 16)
+                } else {
+                    (8, n as usize)
+                };
+                // With several planes selected, the sprite for each plane follows the last
+                let size = width / 8 * height;
+                let planes = self.planes;
+                let range = self.memory_range(
+                    self.i_register as usize,
+                    size * planes.count_ones() as usize,
+                )?;
+                let mut start = range.start;
+                let mut flag = 0;
+                for plane in 0..PLANE_AMOUNT {
+                    if planes >> plane & 1 == 0 {
+                        continue;
                     }
+                    // Copied out so memory isn't borrowed while drawing, at most 16x16
+                    let mut sprite = [0; 32];
+                    sprite[..size].copy_from_slice(&self.memory[start..start + size]);
+                    flag = flag.max(self.blit_sprite(x, y, width, &sprite[..size], plane));
+                    start += size;
+                }
+                self.v_registers[15] = flag;
+                if self.quirks.display_wait {
+                    self.vblank_wait = true;
+                }
+            }
+            Instruction::SkipKey { x } => {
+                if self.keys[(self.v_registers[x as usize] & 0xF) as usize] {
+                    self.skip()?;
+                }
+            }
+            Instruction::SkipNotKey { x } => {
+                if !self.keys[(self.v_registers[x as usize] & 0xF) as usize] {
+                    self.skip()?;
                 }
             }
-            _ => {
-                println!("unimplemented");
+            Instruction::LoadLongI(target) => {
+                self.i_register = target;
+            }
+            Instruction::SelectPlanes(n) => {
+                self.planes = n & ((1 << PLANE_AMOUNT) - 1);
+            }
+            Instruction::LoadAudio => {
+                let range = self.memory_range(self.i_register as usize, AUDIO_PATTERN_SIZE)?;
+                self.audio_pattern.copy_from_slice(&self.memory[range]);
+            }
+            Instruction::LoadDelay { x } => {
+                self.v_registers[x as usize] = self.time_register;
+            }
+            Instruction::WaitKey { x } => {
+                self.key_wait = Some(KeyWait {
+                    register: x as usize,
+                    pressed: None,
+                });
+            }
+            Instruction::SetDelay { x } => {
+                self.time_register = self.v_registers[x as usize];
+            }
+            Instruction::SetSound { x } => {
+                self.sound_register = self.v_registers[x as usize];
+            }
+            Instruction::AddI { x } => {
+                let val = self.v_registers[x as usize];
+                self.i_register = self.i_register.wrapping_add(val as u16);
+            }
+            Instruction::LoadFont { x } => {
+                let digit = self.v_registers[x as usize] & 0xF;
+                self.i_register = self.font_address + digit as u16 * FONT_CHAR_SIZE as u16;
+            }
+            Instruction::LoadBigFont { x } => {
+                let digit = self.v_registers[x as usize] & 0xF;
+                self.i_register =
+                    self.font_address + FONT_SIZE as u16 + digit as u16 * BIG_FONT_CHAR_SIZE as u16;
+            }
+            Instruction::StoreBcd { x } => {
+                let range = self.memory_range(self.i_register as usize, 3)?;
+                let val = self.v_registers[x as usize];
+                self.memory[range].copy_from_slice(&[val / 100, val / 10 % 10, val % 10]);
+            }
+            Instruction::SetPitch { x } => {
+                self.pitch = self.v_registers[x as usize];
+            }
+            Instruction::StoreRegs { x } => {
+                let count = x as usize + 1;
+                let range = self.memory_range(self.i_register as usize, count)?;
+                self.memory[range].copy_from_slice(&self.v_registers[..count]);
+                if self.quirks.memory_increments_i {
+                    self.i_register = self.i_register.wrapping_add(count as u16);
+                }
+            }
+            Instruction::LoadRegs { x } => {
+                let count = x as usize + 1;
+                let range = self.memory_range(self.i_register as usize, count)?;
+                self.v_registers[..count].copy_from_slice(&self.memory[range]);
+                if self.quirks.memory_increments_i {
+                    self.i_register = self.i_register.wrapping_add(count as u16);
+                }
+            }
+            Instruction::StoreFlags { x } => {
+                let count = x as usize + 1;
+                self.flags[..count].copy_from_slice(&self.v_registers[..count]);
+            }
+            Instruction::LoadFlags { x } => {
+                let count = x as usize + 1;
+                self.v_registers[..count].copy_from_slice(&self.flags[..count]);
             }
         }
         if !jumped {
-            self.program_counter += 2;
+            self.program_counter = self.program_counter.wrapping_add(instruction.size());
         }
+        Ok(())
     }
 
-    fn clear_screen(&mut self) {
-        self.pixels.iter_mut().for_each(|p| *p = 0);
+    /// Bounds-checks an access of `len` bytes starting at `address`.
+    fn memory_range(&self, address: usize, len: usize) -> Result<Range<usize>, EmuError> {
+        let end = address + len;
+        if end > self.memory.len() {
+            return Err(EmuError::OutOfBounds {
+                address: address.max(self.memory.len()),
+            });
+        }
+        Ok(address..end)
     }
 
-    fn blit_sprite(&mut self, x: u8, y: u8, sprite: &[u8]) {
-        self.v_registers[15] = 0;
-        for (index, pixel) in sprite.iter().enumerate() {
-            if *pixel == 1 {
-                let mut x = x as usize + index % 8;
-                let mut y = y as usize + index / 8;
-                if x >= SCREEN_WIDTH {
-                    x -= SCREEN_WIDTH;
-                }
-                if y >= SCREEN_HEIGHT {
-                    y -= SCREEN_HEIGHT;
-                }
-                let target = &mut self.pixels[y * SCREEN_WIDTH + x];
-                if *target == 1 {
-                    self.v_registers[15] = 1;
+    /// XORs a sprite of `width`-pixel rows, packed into bytes, onto `plane` and returns
+    /// the value for VF.
+    fn blit_sprite(&mut self, x: u8, y: u8, width: usize, sprite: &[u8], plane: usize) -> u8 {
+        let screen_width = self.display.width();
+        let screen_height = self.display.height();
+        // The sprite origin always wraps, only the parts hanging off the edges are clipped
+        let x = x as usize % screen_width;
+        let y = y as usize % screen_height;
+        let mut collided_rows = 0;
+        let mut clipped_rows = 0;
+        for (row_index, row) in sprite.chunks_exact(width / 8).enumerate() {
+            let mut y = y + row_index;
+            if y >= screen_height {
+                if self.quirks.clip_sprites {
+                    clipped_rows += 1;
+                    continue;
                 }
-                *target ^= *pixel;
+                y -= screen_height;
             }
+            let bits = row.iter().fold(0, |bits, byte| bits << 8 | *byte as u128);
+            let wrap = !self.quirks.clip_sprites;
+            if self.display.xor_row(plane, x, y, bits, width, wrap) {
+                collided_rows += 1;
+            }
+        }
+        if self.quirks.collision_counts_rows && self.is_hires() {
+            collided_rows + clipped_rows
+        } else {
+            (collided_rows > 0) as u8
         }
     }
 }
@@ -0,0 +1,281 @@
+//! Input movies: the keypad state of every frame of a run, along with everything needed
+//! to replay it exactly.
+//!
+//! A movie pins down the random number generator seed and the machine configuration,
+//! and stores the held keys and `System::state_hash` after each frame.
+//! Playing it back on the same ROM must reproduce every hash, so the first frame whose
+//! hash differs is where the playback desynced.
+//!
+//! The text form starts with a header line and the configuration, followed by one line
+//! per frame with the held keys as a bitmask and the state hash, both in hex:
+//!
+//! ```text
+//! rc80-movie 2
+//! seed 0x2a
+//! variant chip8
+//! quirks 0x3b
+//! key-wait press
+//! instructions-per-frame 11
+//! font cosmac-vip 0x050
+//! rom 0x24c0ce867dd76dc3
+//! frames
+//! 0000 c7d5a7b73e3c93fa
+//! 0010 0e20c62f62afbbf6
+//! ```
+
+use std::fmt::Write;
+
+use crate::error::{EmuError, MovieError};
+use crate::font::MAX_FONT_ADDRESS;
+use crate::state::{fnv1a, quirk_bits, quirks_from_bits};
+use crate::{Font, KeyWaitTrigger, Quirks, System, Variant};
+
+/// Version of the text format written by `Movie::to_text`.
+pub const MOVIE_VERSION: u32 = 2;
+
+/// One recorded frame.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MovieFrame {
+    /// Keys held during the frame, bit N set for key N.
+    pub keys: u16,
+    /// `System::state_hash` once the frame has run.
+    pub hash: u64,
+}
+
+/// A recorded run, see the [module documentation](self).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Movie {
+    pub seed: u64,
+    pub variant: Variant,
+    pub quirks: Quirks,
+    pub key_wait_trigger: KeyWaitTrigger,
+    pub instructions_per_frame: u32,
+    pub font: Font,
+    pub font_address: u16,
+    /// Hash of the ROM the movie was recorded on.
+    pub rom_hash: u64,
+    pub frames: Vec<MovieFrame>,
+}
+
+impl Movie {
+    /// Starts an empty movie for `rom`, seeding the random number generator with `seed`
+    /// and taking the rest of the configuration from `sys`.
+    pub fn new(seed: u64, sys: &System, rom: &[u8]) -> Self {
+        Self {
+            seed,
+            variant: sys.variant,
+            quirks: sys.quirks,
+            key_wait_trigger: sys.key_wait_trigger,
+            instructions_per_frame: sys.instructions_per_frame,
+            font: sys.font(),
+            font_address: sys.font_address(),
+            rom_hash: fnv1a(rom),
+            frames: vec![],
+        }
+    }
+
+    /// Creates a fresh system configured like the movie, with `rom` loaded. Recording and
+    /// playback both start from this system.
+    pub fn boot(&self, rom: &[u8]) -> Result<System, MovieError> {
+        if fnv1a(rom) != self.rom_hash {
+            return Err(MovieError::RomMismatch);
+        }
+        let mut sys = System::with_seed(self.seed);
+        sys.set_variant(self.variant);
+        sys.quirks = self.quirks;
+        sys.key_wait_trigger = self.key_wait_trigger;
+        sys.instructions_per_frame = self.instructions_per_frame;
+        sys.set_font(self.font, self.font_address)
+            .and_then(|()| sys.load(rom))
+            .map_err(|error| MovieError::Emu { frame: 0, error })?;
+        Ok(sys)
+    }
+
+    /// Runs a frame of `sys` with the keys currently held and appends it to the movie.
+    /// Nothing is recorded if the frame fails.
+    pub fn record_frame(&mut self, sys: &mut System) -> Result<(), EmuError> {
+        let keys = sys.pressed_keys();
+        sys.run_frame()?;
+        self.frames.push(MovieFrame {
+            keys,
+            hash: sys.state_hash(),
+        });
+        Ok(())
+    }
+
+    /// Holds the keys recorded for frame `index`, runs it on `sys` and checks that the
+    /// resulting state matches the recording.
+    pub fn play_frame(&self, index: usize, sys: &mut System) -> Result<(), MovieError> {
+        let frame = *self
+            .frames
+            .get(index)
+            .ok_or(MovieError::PastEnd { frame: index })?;
+        sys.set_pressed_keys(frame.keys);
+        sys.run_frame().map_err(|error| MovieError::Emu {
+            frame: index,
+            error,
+        })?;
+        let actual = sys.state_hash();
+        if actual != frame.hash {
+            return Err(MovieError::Desync {
+                frame: index,
+                expected: frame.hash,
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    /// Boots `rom` and plays the whole movie on it, returning the system as it is after
+    /// the last frame.
+    pub fn play(&self, rom: &[u8]) -> Result<System, MovieError> {
+        let mut sys = self.boot(rom)?;
+        for index in 0..self.frames.len() {
+            self.play_frame(index, &mut sys)?;
+        }
+        Ok(sys)
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// Renders the text form.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        writeln!(out, "rc80-movie {}", MOVIE_VERSION).unwrap();
+        writeln!(out, "seed {:#x}", self.seed).unwrap();
+        writeln!(out, "variant {}", variant_name(self.variant)).unwrap();
+        writeln!(out, "quirks {:#x}", quirk_bits(&self.quirks)).unwrap();
+        writeln!(out, "key-wait {}", trigger_name(self.key_wait_trigger)).unwrap();
+        writeln!(
+            out,
+            "instructions-per-frame {}",
+            self.instructions_per_frame
+        )
+        .unwrap();
+        writeln!(
+            out,
+            "font {} {:#05x}",
+            font_name(self.font),
+            self.font_address
+        )
+        .unwrap();
+        writeln!(out, "rom {:#018x}", self.rom_hash).unwrap();
+        writeln!(out, "frames").unwrap();
+        for frame in &self.frames {
+            writeln!(out, "{:04x} {:016x}", frame.keys, frame.hash).unwrap();
+        }
+        out
+    }
+
+    /// Parses the text form. Blank lines are ignored.
+    pub fn parse(text: &str) -> Result<Self, MovieError> {
+        let end = MovieError::Parse {
+            line: text.lines().count() + 1,
+        };
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+        let mut field = |name: &str| {
+            let (line, text) = lines.next().ok_or(end)?;
+            let error = MovieError::Parse { line };
+            match text.split_once(' ') {
+                Some((key, value)) if key == name => Ok((value.trim(), error)),
+                None if text == name => Ok(("", error)),
+                _ => Err(error),
+            }
+        };
+
+        let (version, error) = field("rc80-movie")?;
+        if version.parse::<u32>().map_err(|_| error)? != MOVIE_VERSION {
+            return Err(error);
+        }
+        let (seed, error) = field("seed")?;
+        let seed = parse_hex(seed).ok_or(error)?;
+        let (variant, error) = field("variant")?;
+        let variant = [Variant::Chip8, Variant::SuperChip, Variant::XoChip]
+            .into_iter()
+            .find(|candidate| variant_name(*candidate) == variant)
+            .ok_or(error)?;
+        let (quirks, error) = field("quirks")?;
+        let quirks = parse_hex(quirks)
+            .and_then(|bits| u32::try_from(bits).ok())
+            .ok_or(error)?;
+        let (trigger, error) = field("key-wait")?;
+        let key_wait_trigger = [KeyWaitTrigger::Press, KeyWaitTrigger::Release]
+            .into_iter()
+            .find(|candidate| trigger_name(*candidate) == trigger)
+            .ok_or(error)?;
+        let (instructions_per_frame, error) = field("instructions-per-frame")?;
+        let instructions_per_frame = instructions_per_frame.parse().map_err(|_| error)?;
+        let (font, error) = field("font")?;
+        let (font, font_address) = font.split_once(' ').ok_or(error)?;
+        let font = [Font::CosmacVip, Font::Chip48, Font::Dream6800]
+            .into_iter()
+            .find(|candidate| font_name(*candidate) == font)
+            .ok_or(error)?;
+        let font_address = parse_hex(font_address.trim())
+            .filter(|address| *address <= MAX_FONT_ADDRESS as u64)
+            .ok_or(error)? as u16;
+        let (rom_hash, error) = field("rom")?;
+        let rom_hash = parse_hex(rom_hash).ok_or(error)?;
+        field("frames")?;
+
+        let mut frames = vec![];
+        for (line, text) in lines {
+            let error = MovieError::Parse { line };
+            let (keys, hash) = text.split_once(' ').ok_or(error)?;
+            frames.push(MovieFrame {
+                keys: u16::from_str_radix(keys, 16).map_err(|_| error)?,
+                hash: u64::from_str_radix(hash.trim(), 16).map_err(|_| error)?,
+            });
+        }
+        Ok(Self {
+            seed,
+            variant,
+            quirks: quirks_from_bits(quirks),
+            key_wait_trigger,
+            instructions_per_frame,
+            font,
+            font_address,
+            rom_hash,
+            frames,
+        })
+    }
+}
+
+fn parse_hex(text: &str) -> Option<u64> {
+    u64::from_str_radix(text.strip_prefix("0x")?, 16).ok()
+}
+
+fn variant_name(variant: Variant) -> &'static str {
+    match variant {
+        Variant::Chip8 => "chip8",
+        Variant::SuperChip => "superchip",
+        Variant::XoChip => "xochip",
+    }
+}
+
+fn trigger_name(trigger: KeyWaitTrigger) -> &'static str {
+    match trigger {
+        KeyWaitTrigger::Press => "press",
+        KeyWaitTrigger::Release => "release",
+    }
+}
+
+fn font_name(font: Font) -> &'static str {
+    match font {
+        Font::CosmacVip => "cosmac-vip",
+        Font::Chip48 => "chip48",
+        Font::Dream6800 => "dream6800",
+    }
+}
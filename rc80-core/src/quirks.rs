@@ -0,0 +1,69 @@
+/// Behavioural differences between CHIP-8 platforms. Each flag can be toggled on its
+/// own; the constructors provide the combinations used by well-known interpreters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quirks {
+    /// 8XY6/8XYE shift VY into VX instead of shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// FX55/FX65 leave I pointing past the last register stored or loaded.
+    pub memory_increments_i: bool,
+    /// BNNN behaves as BXNN and jumps to XNN + VX instead of NNN + V0.
+    pub jump_uses_vx: bool,
+    /// 8XY1/8XY2/8XY3 reset VF to 0.
+    pub logic_resets_vf: bool,
+    /// DXYN waits for the next frame before execution continues, which limits drawing
+    /// to 60 sprites per second.
+    pub display_wait: bool,
+    /// Sprites are cut off at the screen edges instead of wrapping around.
+    pub clip_sprites: bool,
+    /// In high resolution mode, DXYN sets VF to the number of sprite rows that collided
+    /// or were clipped off the bottom, instead of just 1.
+    pub collision_counts_rows: bool,
+}
+
+impl Quirks {
+    /// The original COSMAC VIP interpreter.
+    pub const fn cosmac_vip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            memory_increments_i: true,
+            jump_uses_vx: false,
+            logic_resets_vf: true,
+            display_wait: true,
+            clip_sprites: true,
+            collision_counts_rows: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1 on the HP-48 calculators.
+    pub const fn super_chip() -> Self {
+        Self {
+            shift_uses_vy: false,
+            memory_increments_i: false,
+            jump_uses_vx: true,
+            logic_resets_vf: false,
+            display_wait: false,
+            clip_sprites: true,
+            collision_counts_rows: true,
+        }
+    }
+
+    /// XO-CHIP as implemented by Octo.
+    pub const fn xo_chip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            memory_increments_i: true,
+            jump_uses_vx: false,
+            logic_resets_vf: false,
+            display_wait: false,
+            clip_sprites: false,
+            collision_counts_rows: false,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self::cosmac_vip()
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::VecDeque;
+
+use crate::error::StateError;
+use crate::System;
+
+/// Frames between snapshots unless told otherwise.
+pub const DEFAULT_REWIND_INTERVAL: u32 = 2;
+/// Memory budget for snapshots unless told otherwise, in bytes.
+pub const DEFAULT_REWIND_BUDGET: usize = 16 * 1024 * 1024;
+
+/// History of save states taken every few frames, for stepping backwards in time.
+///
+/// Only the newest snapshot is kept in full. Each older one is stored as the XOR with
+/// the snapshot after it, run-length encoded, which is tiny since consecutive frames
+/// barely differ. Snapshots of different sizes, as left by a variant switch, chain the
+/// same way. When the history outgrows its memory budget, the oldest snapshots are
+/// dropped.
+#[derive(Clone, Debug)]
+pub struct Rewind {
+    interval: u32,
+    budget: usize,
+    frames: u32,
+    newest: Option<Vec<u8>>,
+    /// Oldest first. The last delta leads from `newest` to the snapshot before it.
+    deltas: VecDeque<Vec<u8>>,
+    delta_bytes: usize,
+}
+
+impl Default for Rewind {
+    fn default() -> Self {
+        Self::new(DEFAULT_REWIND_INTERVAL, DEFAULT_REWIND_BUDGET)
+    }
+}
+
+impl Rewind {
+    /// Creates an empty history that snapshots every `interval` frames and keeps at most
+    /// about `budget` bytes.
+    pub fn new(interval: u32, budget: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            budget,
+            frames: 0,
+            newest: None,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+        }
+    }
+
+    /// Meant to be called once per frame. Takes a snapshot of `sys` every `interval`
+    /// calls.
+    pub fn record(&mut self, sys: &System) {
+        self.frames += 1;
+        if self.frames >= self.interval {
+            self.frames = 0;
+            self.push(sys.save_state());
+        }
+    }
+
+    /// Restores the newest snapshot into `sys` and forgets it, so repeated calls walk
+    /// backwards. Returns `false` once the history is exhausted. If the snapshot doesn't
+    /// load, `sys` is left untouched and the history cleared.
+    pub fn rewind(&mut self, sys: &mut System) -> Result<bool, StateError> {
+        let Some(newest) = self.newest.take() else {
+            return Ok(false);
+        };
+        if let Err(err) = sys.load_state(&newest) {
+            self.clear();
+            return Err(err);
+        }
+        if let Some(delta) = self.deltas.pop_back() {
+            self.delta_bytes -= delta.len();
+            self.newest = Some(apply_delta(newest, &delta));
+        }
+        self.frames = 0;
+        Ok(true)
+    }
+
+    /// Number of snapshots that can be rewound to.
+    pub fn len(&self) -> usize {
+        self.newest.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    /// Approximate memory taken by the snapshots, in bytes.
+    pub fn memory_used(&self) -> usize {
+        self.newest.as_ref().map_or(0, Vec::len) + self.delta_bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+        self.frames = 0;
+    }
+
+    fn push(&mut self, state: Vec<u8>) {
+        if let Some(previous) = self.newest.take() {
+            let delta = encode_delta(&previous, &state);
+            self.delta_bytes += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.newest = Some(state);
+        while self.memory_used() > self.budget {
+            let Some(oldest) = self.deltas.pop_front() else {
+                break;
+            };
+            self.delta_bytes -= oldest.len();
+        }
+    }
+}
+
+/// Encodes the lengths of `old` and `new`, then `old ^ new` as pairs of a zero run
+/// length and a literal run, followed by the literal bytes. The shorter state counts as
+/// padded with zeros. All lengths are LEB128 varints.
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    write_varint(&mut out, old.len());
+    write_varint(&mut out, new.len());
+    let len = old.len().max(new.len());
+    let xor = |index| old.get(index).unwrap_or(&0) ^ new.get(index).unwrap_or(&0);
+    let mut index = 0;
+    while index < len {
+        let zeros = (index..len).take_while(|i| xor(*i) == 0).count();
+        index += zeros;
+        let literals = (index..len).take_while(|i| xor(*i) != 0).count();
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, literals);
+        out.extend((index..index + literals).map(xor));
+        index += literals;
+    }
+    out
+}
+
+/// Turns either state a delta was encoded from back into the other one.
+fn apply_delta(mut state: Vec<u8>, delta: &[u8]) -> Vec<u8> {
+    let mut delta = delta;
+    let first = read_varint(&mut delta);
+    let second = read_varint(&mut delta);
+    let target = if state.len() == first { second } else { first };
+    state.resize(first.max(second), 0);
+    let mut index = 0;
+    while !delta.is_empty() {
+        index += read_varint(&mut delta);
+        let literals = read_varint(&mut delta);
+        for (byte, xor) in state[index..index + literals].iter_mut().zip(delta) {
+            *byte ^= xor;
+        }
+        delta = &delta[literals..];
+        index += literals;
+    }
+    state.truncate(target);
+    state
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(input: &mut &[u8]) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = input[0];
+        *input = &input[1..];
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Variant;
+
+    /// Checks that a delta between `old` and `new` leads from either one to the other.
+    fn assert_delta_round_trips(old: &[u8], new: &[u8]) {
+        let delta = encode_delta(old, new);
+        assert_eq!(apply_delta(new.to_vec(), &delta), old);
+        assert_eq!(apply_delta(old.to_vec(), &delta), new);
+    }
+
+    #[test]
+    fn deltas_round_trip() {
+        assert_delta_round_trips(&[], &[]);
+        assert_delta_round_trips(&[1, 2, 3, 4], &[1, 2, 3, 4]);
+        assert_delta_round_trips(&[1, 2, 3, 4], &[1, 0, 3, 5]);
+        assert_delta_round_trips(&[0; 8], &[0xFF; 8]);
+    }
+
+    #[test]
+    fn deltas_round_trip_runs_longer_than_a_varint_byte() {
+        // Runs of 128 bytes and more take several bytes to encode
+        let old: Vec<u8> = (0..40_000).map(|index| (index % 251) as u8).collect();
+        let mut new = old.clone();
+        new[200..500].iter_mut().for_each(|byte| *byte ^= 0x5A);
+        new[20_000..37_000]
+            .iter_mut()
+            .for_each(|byte| *byte ^= 0xFF);
+        assert_delta_round_trips(&old, &new);
+        // Long zero runs stay small
+        assert!(encode_delta(&old, &old).len() < 16);
+    }
+
+    #[test]
+    fn deltas_round_trip_states_of_different_lengths() {
+        assert_delta_round_trips(&[1, 2, 3], &[1, 2, 3, 4, 5]);
+        assert_delta_round_trips(&[1, 2, 3, 0, 0], &[1, 2]);
+        assert_delta_round_trips(&[], &[7; 300]);
+    }
+
+    #[test]
+    fn rewinds_across_variant_switches() {
+        let mut sys = System::default();
+        sys.load(&[0x60, 0x01]).unwrap();
+        let mut history = Rewind::new(1, usize::MAX);
+        history.record(&sys);
+        let chip8 = sys.save_state();
+        sys.set_variant(Variant::XoChip);
+        history.record(&sys);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.rewind(&mut sys), Ok(true));
+        assert_eq!(history.rewind(&mut sys), Ok(true));
+        assert_eq!(sys.save_state(), chip8);
+        assert_eq!(history.rewind(&mut sys), Ok(false));
+    }
+}
@@ -0,0 +1,106 @@
+/// Seed used by `System::default()`.
+pub const DEFAULT_SEED: u64 = 0x2545_f491_4f6c_dd1d;
+
+/// Source of random bytes for the CXNN instruction.
+pub trait Rng: Send {
+    fn next_u8(&mut self) -> u8;
+
+    /// Returns the internal state so it can be kept in a save state, if the generator
+    /// supports it.
+    fn snapshot(&self) -> Option<u64> {
+        None
+    }
+
+    /// Restores a state previously returned by `snapshot`.
+    fn restore(&mut self, _state: u64) {}
+
+    /// Returns a copy of the generator for `System::clone`, if it supports it.
+    fn clone_rng(&self) -> Option<Box<dyn Rng>> {
+        None
+    }
+}
+
+/// Small, seedable xorshift64* generator. The same seed always produces the same
+/// sequence, which keeps replays and tests deterministic.
+#[derive(Clone, Debug)]
+pub struct XorShiftRng {
+    state: u64,
+}
+
+impl XorShiftRng {
+    pub fn new(seed: u64) -> Self {
+        // An all-zero state would only ever produce zeroes
+        let state = if seed == 0 { DEFAULT_SEED } else { seed };
+        Self { state }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+/// Stand-in for a generator that can't be cloned or serialized: an `XorShiftRng`
+/// restored to `snapshot` if there is one, or seeded with `DEFAULT_SEED`.
+pub(crate) fn replacement(snapshot: Option<u64>) -> Box<dyn Rng> {
+    let mut rng = XorShiftRng::default();
+    if let Some(state) = snapshot {
+        rng.restore(state);
+    }
+    Box::new(rng)
+}
+
+impl Default for XorShiftRng {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
+
+impl Rng for XorShiftRng {
+    fn next_u8(&mut self) -> u8 {
+        (self.next_u64() >> 56) as u8
+    }
+
+    fn snapshot(&self) -> Option<u64> {
+        Some(self.state)
+    }
+
+    fn restore(&mut self, state: u64) {
+        *self = Self::new(state);
+    }
+
+    fn clone_rng(&self) -> Option<Box<dyn Rng>> {
+        Some(Box::new(self.clone()))
+    }
+}
+
+/// Serializes a generator as its `snapshot`. Only the state survives, not the kind of
+/// generator: deserializing always gives an `XorShiftRng`, restored to the saved state
+/// if there is one, so a custom generator has to be set again with `System::set_rng`.
+#[cfg(feature = "serde")]
+pub(crate) mod serde_state {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Rng;
+
+    // serde hands over the field itself, so this can't take a `&dyn Rng`
+    #[allow(clippy::borrowed_box)]
+    pub(crate) fn serialize<S: Serializer>(
+        rng: &Box<dyn Rng>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        rng.snapshot().serialize(serializer)
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Box<dyn Rng>, D::Error> {
+        Ok(super::replacement(Option::<u64>::deserialize(
+            deserializer,
+        )?))
+    }
+}
@@ -0,0 +1,426 @@
+//! Save states: a compact binary snapshot of the whole machine.
+//!
+//! A state starts with the magic bytes `RC80`, followed by the format version as a
+//! little-endian `u16` and then the fields in a fixed order, all little-endian. New
+//! fields are only ever added at the end under a new version, so older states stay
+//! loadable and get defaults for whatever they don't contain.
+//!
+//! Version 1 holds, in order: variant, quirks, key wait trigger, instructions per
+//! frame, font and font address; the program counter, stack pointer, stack, memory, V
+//! registers, I, timers, RPL flags, audio pattern and pitch; the keypad and any pending
+//! FX0A wait; the vblank wait, halted and selected plane flags; the display; and the
+//! random number generator state, if it could be captured.
+//!
+//! The tracer is not part of the state.
+//!
+//! To add a field, bump `STATE_VERSION`, write the field after the existing ones, and
+//! in `load_state` read it only when the state's version has it, falling back to a
+//! default otherwise. `tests/fixtures` keeps a state of every version, which must keep
+//! loading.
+
+use crate::error::StateError;
+use crate::font::MAX_FONT_ADDRESS;
+use crate::{
+    Display, Font, KeyWait, KeyWaitTrigger, Quirks, System, Variant, XorShiftRng,
+    HIRES_SCREEN_HEIGHT, HIRES_SCREEN_WIDTH, KEY_AMOUNT, PLANE_AMOUNT, REGISTER_AMOUNT,
+    SCREEN_HEIGHT, SCREEN_WIDTH, STACK_SIZE,
+};
+
+const MAGIC: &[u8; 4] = b"RC80";
+/// Version of the save state format written by `System::save_state`.
+pub const STATE_VERSION: u16 = 1;
+
+impl System {
+    /// Captures the full machine state, see the [module documentation](self).
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Writer(Vec::with_capacity(self.memory.len() + 2048));
+        out.bytes(MAGIC);
+        out.u16(STATE_VERSION);
+
+        out.u8(match self.variant {
+            Variant::Chip8 => 0,
+            Variant::SuperChip => 1,
+            Variant::XoChip => 2,
+        });
+        out.u32(quirk_bits(&self.quirks));
+        out.u8(match self.key_wait_trigger {
+            KeyWaitTrigger::Press => 0,
+            KeyWaitTrigger::Release => 1,
+        });
+        out.u32(self.instructions_per_frame);
+        out.u8(match self.font {
+            Font::CosmacVip => 0,
+            Font::Chip48 => 1,
+            Font::Dream6800 => 2,
+        });
+        out.u16(self.font_address);
+
+        out.u16(self.program_counter);
+        out.u8(self.stack_pointer);
+        self.stack.iter().for_each(|address| out.u16(*address));
+        out.u32(self.memory.len() as u32);
+        out.bytes(&self.memory);
+        out.bytes(&self.v_registers);
+        out.u16(self.i_register);
+        out.u8(self.time_register);
+        out.u8(self.sound_register);
+        out.bytes(&self.flags);
+        out.bytes(&self.audio_pattern);
+        out.u8(self.pitch);
+
+        out.u16(self.pressed_keys());
+        match self.key_wait {
+            None => out.u8(0),
+            Some(KeyWait { register, pressed }) => {
+                out.u8(1);
+                out.u8(register as u8);
+                out.u8(pressed.unwrap_or(0xFF));
+            }
+        }
+        out.u8(self.vblank_wait as u8);
+        out.u8(self.halted as u8);
+        out.u8(self.planes);
+
+        out.u16(self.display.width() as u16);
+        out.u16(self.display.height() as u16);
+        out.u8(self.display.plane_count() as u8);
+        for row in self.display.packed_rows() {
+            out.bytes(&row.to_le_bytes());
+        }
+
+        match self.rng.snapshot() {
+            None => out.u8(0),
+            Some(state) => {
+                out.u8(1);
+                out.bytes(&state.to_le_bytes());
+            }
+        }
+        out.0
+    }
+
+    /// Hashes the machine state, leaving out the configuration. Two systems with the
+    /// same hash are in the same state, barring collisions.
+    ///
+    /// Movies store these hashes, so unlike `save_state` the hashed fields and their
+    /// encoding are frozen: changing them invalidates every recorded movie and needs a
+    /// new `MOVIE_VERSION`.
+    pub fn state_hash(&self) -> u64 {
+        let mut out = Writer(Vec::with_capacity(self.memory.len() + 2048));
+        out.u16(self.program_counter);
+        out.u8(self.stack_pointer);
+        self.stack.iter().for_each(|address| out.u16(*address));
+        out.bytes(&self.memory);
+        out.bytes(&self.v_registers);
+        out.u16(self.i_register);
+        out.u8(self.time_register);
+        out.u8(self.sound_register);
+        out.bytes(&self.flags);
+        out.bytes(&self.audio_pattern);
+        out.u8(self.pitch);
+        out.u16(self.pressed_keys());
+        match self.key_wait {
+            None => out.u8(0),
+            Some(KeyWait { register, pressed }) => {
+                out.u8(1);
+                out.u8(register as u8);
+                out.u8(pressed.unwrap_or(0xFF));
+            }
+        }
+        out.u8(self.vblank_wait as u8);
+        out.u8(self.halted as u8);
+        out.u8(self.planes);
+        out.u16(self.display.width() as u16);
+        out.u16(self.display.height() as u16);
+        out.bytes(&self.display.to_pixels());
+        out.bytes(&self.rng.snapshot().unwrap_or(0).to_le_bytes());
+        fnv1a(&out.0)
+    }
+
+    /// Restores a state returned by `save_state`. On error, the system is left
+    /// untouched. The random number generator keeps its current state if the saved one
+    /// wasn't captured.
+    pub fn load_state(&mut self, bytes: &[u8]) -> Result<(), StateError> {
+        let mut input = Reader(bytes);
+        if input.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(StateError::NotAState);
+        }
+        let version = input.u16()?;
+        if !(1..=STATE_VERSION).contains(&version) {
+            return Err(StateError::UnsupportedVersion { version });
+        }
+
+        // Version 1 fields, present in every state
+
+        let variant = match input.u8()? {
+            0 => Variant::Chip8,
+            1 => Variant::SuperChip,
+            2 => Variant::XoChip,
+            _ => return Err(invalid("variant")),
+        };
+        let quirks = quirks_from_bits(input.u32()?);
+        let key_wait_trigger = match input.u8()? {
+            0 => KeyWaitTrigger::Press,
+            1 => KeyWaitTrigger::Release,
+            _ => return Err(invalid("key wait trigger")),
+        };
+        let instructions_per_frame = input.u32()?;
+        let font = match input.u8()? {
+            0 => Font::CosmacVip,
+            1 => Font::Chip48,
+            2 => Font::Dream6800,
+            _ => return Err(invalid("font")),
+        };
+        let font_address = input.u16()?;
+
+        let program_counter = input.u16()?;
+        let stack_pointer = input.u8()?;
+        let mut stack = [0; STACK_SIZE];
+        for address in &mut stack {
+            *address = input.u16()?;
+        }
+        let memory_size = input.u32()? as usize;
+        if memory_size != variant.memory_size() {
+            return Err(invalid("memory size"));
+        }
+        let memory = input.take(memory_size)?.to_vec();
+        let v_registers = input.array()?;
+        let i_register = input.u16()?;
+        let time_register = input.u8()?;
+        let sound_register = input.u8()?;
+        let flags = input.array()?;
+        let audio_pattern = input.array()?;
+        let pitch = input.u8()?;
+
+        let key_bits = input.u16()?;
+        let mut keys = [false; KEY_AMOUNT];
+        for (key, down) in keys.iter_mut().enumerate() {
+            *down = key_bits >> key & 1 == 1;
+        }
+        let key_wait = match input.u8()? {
+            0 => None,
+            1 => {
+                let register = input.u8()? as usize;
+                let pressed = match input.u8()? {
+                    0xFF => None,
+                    key => Some(key),
+                };
+                Some(KeyWait { register, pressed })
+            }
+            _ => return Err(invalid("key wait")),
+        };
+        let vblank_wait = input.bool()?;
+        let halted = input.bool()?;
+        let planes = input.u8()?;
+
+        let width = input.u16()? as usize;
+        let height = input.u16()? as usize;
+        let plane_count = input.u8()? as usize;
+        // Checked again below, but the row count has to be sane before reading them
+        if !RESOLUTIONS.contains(&(width, height)) {
+            return Err(invalid("resolution"));
+        }
+        if plane_count != PLANE_AMOUNT {
+            return Err(invalid("plane count"));
+        }
+        let mut rows = Vec::with_capacity(height * plane_count);
+        for _ in 0..height * plane_count {
+            rows.push(u128::from_le_bytes(input.array()?));
+        }
+        let display = Display::from_packed_rows(width, height, plane_count, rows)
+            .ok_or(invalid("display"))?;
+
+        let rng_state = match input.u8()? {
+            0 => None,
+            1 => Some(u64::from_le_bytes(input.array()?)),
+            _ => return Err(invalid("random number generator state")),
+        };
+
+        // Fields added by later versions go here, each read under `if version >= N`
+        // with a default for older states
+
+        if !input.0.is_empty() {
+            return Err(invalid("length"));
+        }
+
+        let loaded = System {
+            program_counter,
+            stack_pointer,
+            stack,
+            memory,
+            v_registers,
+            i_register,
+            time_register,
+            sound_register,
+            flags,
+            audio_pattern,
+            pitch,
+            variant,
+            key_wait_trigger,
+            quirks,
+            instructions_per_frame,
+            keys,
+            key_wait,
+            vblank_wait,
+            display,
+            halted,
+            planes,
+            font,
+            font_address,
+            // The generator and tracer stay, the generator state is restored below
+            rng: Box::new(XorShiftRng::default()),
+            tracer: None,
+        };
+        loaded.check_invariants()?;
+        let rng = std::mem::replace(&mut self.rng, loaded.rng);
+        let tracer = self.tracer.take();
+        *self = System {
+            rng,
+            tracer,
+            ..loaded
+        };
+        if let Some(state) = rng_state {
+            self.rng.restore(state);
+        }
+        Ok(())
+    }
+
+    /// Checks what the emulator relies on but the fields alone can't guarantee, for
+    /// machines put back together from saved data. Both save states and the serde
+    /// form go through here.
+    pub(crate) fn check_invariants(&self) -> Result<(), StateError> {
+        if self.font_address > MAX_FONT_ADDRESS {
+            return Err(invalid("font address"));
+        }
+        if self.stack_pointer as usize > STACK_SIZE {
+            return Err(invalid("stack pointer"));
+        }
+        if self.memory.len() != self.variant.memory_size() {
+            return Err(invalid("memory size"));
+        }
+        if self.program_counter as usize >= self.memory.len() {
+            return Err(invalid("program counter"));
+        }
+        if let Some(KeyWait { register, pressed }) = self.key_wait {
+            if register >= REGISTER_AMOUNT {
+                return Err(invalid("key wait register"));
+            }
+            if pressed.is_some_and(|key| key as usize >= KEY_AMOUNT) {
+                return Err(invalid("pressed key"));
+            }
+        }
+        if self.planes as usize >= 1 << PLANE_AMOUNT {
+            return Err(invalid("plane selection"));
+        }
+        let display = &self.display;
+        if !RESOLUTIONS.contains(&(display.width(), display.height())) {
+            return Err(invalid("resolution"));
+        }
+        if display.plane_count() != PLANE_AMOUNT {
+            return Err(invalid("plane count"));
+        }
+        Ok(())
+    }
+}
+
+/// Display sizes a machine can be in, low and high resolution.
+const RESOLUTIONS: [(usize, usize); 2] = [
+    (SCREEN_WIDTH, SCREEN_HEIGHT),
+    (HIRES_SCREEN_WIDTH, HIRES_SCREEN_HEIGHT),
+];
+
+/// 64-bit FNV-1a, a fast hash that is stable across platforms and releases.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn invalid(field: &'static str) -> StateError {
+    StateError::Invalid { field }
+}
+
+/// Packs the quirks into bits, in declaration order.
+pub(crate) fn quirk_bits(quirks: &Quirks) -> u32 {
+    [
+        quirks.shift_uses_vy,
+        quirks.memory_increments_i,
+        quirks.jump_uses_vx,
+        quirks.logic_resets_vf,
+        quirks.display_wait,
+        quirks.clip_sprites,
+        quirks.collision_counts_rows,
+    ]
+    .iter()
+    .enumerate()
+    .fold(0, |bits, (index, set)| bits | (*set as u32) << index)
+}
+
+pub(crate) fn quirks_from_bits(bits: u32) -> Quirks {
+    let bit = |index: u32| bits >> index & 1 == 1;
+    Quirks {
+        shift_uses_vy: bit(0),
+        memory_increments_i: bit(1),
+        jump_uses_vx: bit(2),
+        logic_resets_vf: bit(3),
+        display_wait: bit(4),
+        clip_sprites: bit(5),
+        collision_counts_rows: bit(6),
+    }
+}
+
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.0.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("flag")),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+}
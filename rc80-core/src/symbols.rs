@@ -0,0 +1,79 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Named addresses in a program, as produced by the assembler.
+///
+/// The text form has one symbol per line, an address in hex followed by its name:
+///
+/// ```text
+/// 0x0200 main
+/// 0x0216 draw_player
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolMap {
+    symbols: BTreeMap<String, u16>,
+}
+
+impl SymbolMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, address: u16) {
+        self.symbols.insert(name.into(), address);
+    }
+
+    pub fn get(&self, name: &str) -> Option<u16> {
+        self.symbols.get(name).copied()
+    }
+
+    /// Returns the first symbol, in name order, located at `address`.
+    pub fn name_at(&self, address: u16) -> Option<&str> {
+        self.symbols
+            .iter()
+            .find(|(_, symbol_address)| **symbol_address == address)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, u16)> {
+        self.symbols
+            .iter()
+            .map(|(name, address)| (name.as_str(), *address))
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    /// Parses the text form. Blank lines and lines starting with `#` are ignored.
+    /// On failure, returns the 1-based number of the offending line.
+    pub fn parse(text: &str) -> Result<Self, usize> {
+        let mut map = Self::new();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (address, name) = line.split_once(char::is_whitespace).ok_or(index + 1)?;
+            let address = address.trim_start_matches("0x");
+            let address = u16::from_str_radix(address, 16).map_err(|_| index + 1)?;
+            map.insert(name.trim(), address);
+        }
+        Ok(map)
+    }
+
+    /// Renders the text form, sorted by address.
+    pub fn to_text(&self) -> String {
+        let mut symbols: Vec<_> = self.iter().collect();
+        symbols.sort_by_key(|(name, address)| (*address, *name));
+        let mut out = String::new();
+        for (name, address) in symbols {
+            writeln!(out, "{:#06x} {}", address, name).unwrap();
+        }
+        out
+    }
+}
@@ -0,0 +1,779 @@
+//! Unit tests for every instruction, run through `System::step`.
+
+use crate::*;
+
+/// Loads `program`, one opcode per word, into a system for `variant`.
+fn load(variant: Variant, program: &[u16]) -> System {
+    let mut sys = System::with_variant(variant);
+    let bytes: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+    sys.load(&bytes).unwrap();
+    sys
+}
+
+fn steps(sys: &mut System, count: usize) {
+    for _ in 0..count {
+        sys.step().unwrap();
+    }
+}
+
+/// Runs every instruction of a straight-line CHIP-8 `program`.
+fn run(program: &[u16]) -> System {
+    let mut sys = load(Variant::Chip8, program);
+    steps(&mut sys, program.len());
+    sys
+}
+
+fn lit(sys: &System) -> Vec<(usize, usize)> {
+    sys.display().lit_pixels().map(|(x, y, _)| (x, y)).collect()
+}
+
+// Flow control
+
+#[test]
+fn sys_is_ignored() {
+    let sys = run(&[0x0123]);
+    assert_eq!(sys.program_counter, 0x202);
+}
+
+#[test]
+fn jump() {
+    let sys = run(&[0x1456]);
+    assert_eq!(sys.program_counter, 0x456);
+}
+
+#[test]
+fn call_and_return() {
+    let mut sys = load(Variant::Chip8, &[0x2204, 0x0000, 0x00EE]);
+    sys.step().unwrap();
+    assert_eq!(sys.program_counter, 0x204);
+    assert_eq!(sys.stack_pointer, 1);
+    assert_eq!(sys.stack[0], 0x200);
+    sys.step().unwrap();
+    assert_eq!(sys.program_counter, 0x202);
+    assert_eq!(sys.stack_pointer, 0);
+}
+
+#[test]
+fn call_overflows_a_full_stack() {
+    let mut sys = load(Variant::Chip8, &[0x2200]);
+    steps(&mut sys, STACK_SIZE);
+    assert_eq!(sys.step(), Err(EmuError::StackOverflow { address: 0x200 }));
+    assert_eq!(sys.stack_pointer as usize, STACK_SIZE);
+    assert_eq!(sys.program_counter, 0x200);
+}
+
+#[test]
+fn return_underflows_an_empty_stack() {
+    let mut sys = load(Variant::Chip8, &[0x00EE]);
+    assert_eq!(sys.step(), Err(EmuError::StackUnderflow { address: 0x200 }));
+    assert_eq!(sys.program_counter, 0x200);
+}
+
+#[test]
+fn jump_offset_uses_v0() {
+    let sys = run(&[0x6010, 0x6320, 0xB300]);
+    assert_eq!(sys.program_counter, 0x310);
+}
+
+#[test]
+fn jump_offset_uses_vx_with_quirk() {
+    let mut sys = load(Variant::SuperChip, &[0x6010, 0x6320, 0xB300]);
+    steps(&mut sys, 3);
+    assert_eq!(sys.program_counter, 0x320);
+}
+
+#[test]
+fn exit_halts() {
+    let mut sys = load(Variant::SuperChip, &[0x00FD, 0x6001]);
+    steps(&mut sys, 2);
+    assert!(sys.is_halted());
+    assert_eq!(sys.program_counter, 0x200);
+    assert_eq!(sys.v_registers[0], 0);
+}
+
+#[test]
+fn superchip_instructions_are_invalid_on_chip8() {
+    let mut sys = load(Variant::Chip8, &[0x00FD]);
+    assert_eq!(
+        sys.step(),
+        Err(EmuError::InvalidOpcode {
+            address: 0x200,
+            opcode: 0x00FD
+        })
+    );
+}
+
+#[test]
+fn invalid_opcode() {
+    let mut sys = load(Variant::Chip8, &[0x6001, 0x5121]);
+    sys.step().unwrap();
+    assert_eq!(
+        sys.step(),
+        Err(EmuError::InvalidOpcode {
+            address: 0x202,
+            opcode: 0x5121
+        })
+    );
+    assert_eq!(sys.program_counter, 0x202);
+}
+
+#[test]
+fn fetch_past_the_end_of_memory() {
+    let mut sys = run(&[0x1FFF]);
+    assert_eq!(
+        sys.step(),
+        Err(EmuError::OutOfBounds {
+            address: MEMORY_SIZE
+        })
+    );
+}
+
+#[test]
+fn leaving_memory_fails_without_effect() {
+    // Falling through the last instruction
+    let mut sys = run(&[0x1FFE]);
+    sys.memory[0xFFE..].copy_from_slice(&[0x60, 0x01]);
+    assert_eq!(sys.step(), Err(EmuError::OutOfBounds { address: 0x1000 }));
+    assert_eq!((sys.program_counter, sys.v_registers[0]), (0xFFE, 0));
+    // Skipping past it
+    let mut sys = run(&[0x1FFC]);
+    sys.memory[0xFFC..].copy_from_slice(&[0x30, 0x00, 0x00, 0xE0]);
+    assert_eq!(sys.step(), Err(EmuError::OutOfBounds { address: 0x1000 }));
+    assert_eq!(sys.program_counter, 0xFFC);
+    // Jumping with an offset
+    let mut sys = run(&[0x60FF]);
+    sys.memory[0x202..0x204].copy_from_slice(&[0xBF, 0xFF]);
+    assert_eq!(sys.step(), Err(EmuError::OutOfBounds { address: 0x10FE }));
+    assert_eq!(sys.program_counter, 0x202);
+    // Returning behind a call in the last instruction
+    let mut sys = run(&[0x1FFE]);
+    sys.memory[0xFFE..].copy_from_slice(&[0x22, 0x04]);
+    sys.memory[0x204..0x206].copy_from_slice(&[0x00, 0xEE]);
+    steps(&mut sys, 1);
+    assert_eq!(sys.step(), Err(EmuError::OutOfBounds { address: 0x1000 }));
+    assert_eq!((sys.program_counter, sys.stack_pointer), (0x204, 1));
+}
+
+// Skips
+
+#[test]
+fn skip_eq_byte() {
+    assert_eq!(run(&[0x6042, 0x3042]).program_counter, 0x206);
+    assert_eq!(run(&[0x6042, 0x3043]).program_counter, 0x204);
+}
+
+#[test]
+fn skip_ne_byte() {
+    assert_eq!(run(&[0x6042, 0x4043]).program_counter, 0x206);
+    assert_eq!(run(&[0x6042, 0x4042]).program_counter, 0x204);
+}
+
+#[test]
+fn skip_eq_reg() {
+    assert_eq!(run(&[0x6042, 0x6142, 0x5010]).program_counter, 0x208);
+    assert_eq!(run(&[0x6042, 0x6143, 0x5010]).program_counter, 0x206);
+}
+
+#[test]
+fn skip_ne_reg() {
+    assert_eq!(run(&[0x6042, 0x6143, 0x9010]).program_counter, 0x208);
+    assert_eq!(run(&[0x6042, 0x6142, 0x9010]).program_counter, 0x206);
+}
+
+#[test]
+fn skip_steps_over_long_load_on_xochip() {
+    let mut sys = load(Variant::XoChip, &[0x3000, 0xF000, 0x1234, 0x6001]);
+    sys.step().unwrap();
+    assert_eq!(sys.program_counter, 0x206);
+}
+
+#[test]
+fn skip_key() {
+    // Only the low nibble of VX selects the key
+    let mut sys = load(Variant::Chip8, &[0x6015, 0xE09E, 0xE09E]);
+    steps(&mut sys, 2);
+    assert_eq!(sys.program_counter, 0x204);
+    sys.press_key(5);
+    sys.step().unwrap();
+    assert_eq!(sys.program_counter, 0x208);
+}
+
+#[test]
+fn skip_not_key() {
+    let mut sys = load(Variant::Chip8, &[0x6005, 0xE0A1, 0xE0A1]);
+    steps(&mut sys, 2);
+    assert_eq!(sys.program_counter, 0x206);
+    sys.press_key(5);
+    sys.step().unwrap();
+    assert_eq!(sys.program_counter, 0x208);
+}
+
+// Registers and arithmetic
+
+#[test]
+fn load_byte_and_reg() {
+    let sys = run(&[0x6A42, 0x8BA0]);
+    assert_eq!(sys.v_registers[0xA], 0x42);
+    assert_eq!(sys.v_registers[0xB], 0x42);
+}
+
+#[test]
+fn add_byte_wraps_without_touching_vf() {
+    let sys = run(&[0x60FF, 0x6F05, 0x7002]);
+    assert_eq!(sys.v_registers[0], 1);
+    assert_eq!(sys.v_registers[0xF], 5);
+}
+
+#[test]
+fn logic_ops_reset_vf_with_quirk() {
+    for (op, expected) in [(0x8011, 0b1110), (0x8012, 0b1000), (0x8013, 0b0110)] {
+        let sys = run(&[0x600C, 0x610A, 0x6F05, op]);
+        assert_eq!(sys.v_registers[0], expected, "{:04x}", op);
+        assert_eq!(sys.v_registers[0xF], 0, "{:04x}", op);
+    }
+}
+
+#[test]
+fn logic_ops_keep_vf_without_quirk() {
+    let mut sys = load(Variant::SuperChip, &[0x600C, 0x610A, 0x6F05, 0x8011]);
+    steps(&mut sys, 4);
+    assert_eq!(sys.v_registers[0], 0b1110);
+    assert_eq!(sys.v_registers[0xF], 5);
+}
+
+#[test]
+fn add_reg_sets_carry() {
+    let sys = run(&[0x60FF, 0x6102, 0x8014]);
+    assert_eq!(sys.v_registers[0], 1);
+    assert_eq!(sys.v_registers[0xF], 1);
+    let sys = run(&[0x6001, 0x6102, 0x6F07, 0x8014]);
+    assert_eq!(sys.v_registers[0], 3);
+    assert_eq!(sys.v_registers[0xF], 0);
+}
+
+#[test]
+fn sub_sets_not_borrow() {
+    let sys = run(&[0x6005, 0x6103, 0x8015]);
+    assert_eq!(sys.v_registers[0], 2);
+    assert_eq!(sys.v_registers[0xF], 1);
+    let sys = run(&[0x6003, 0x6105, 0x8015]);
+    assert_eq!(sys.v_registers[0], 0xFE);
+    assert_eq!(sys.v_registers[0xF], 0);
+    let sys = run(&[0x6005, 0x6105, 0x8015]);
+    assert_eq!(sys.v_registers[0], 0);
+    assert_eq!(sys.v_registers[0xF], 1);
+}
+
+#[test]
+fn subn_sets_not_borrow() {
+    let sys = run(&[0x6003, 0x6105, 0x8017]);
+    assert_eq!(sys.v_registers[0], 2);
+    assert_eq!(sys.v_registers[0xF], 1);
+    let sys = run(&[0x6005, 0x6103, 0x8017]);
+    assert_eq!(sys.v_registers[0], 0xFE);
+    assert_eq!(sys.v_registers[0xF], 0);
+}
+
+#[test]
+fn shifts_use_vy_with_quirk() {
+    let sys = run(&[0x6001, 0x6181, 0x8016]);
+    assert_eq!(sys.v_registers[0], 0x40);
+    assert_eq!(sys.v_registers[0xF], 1);
+    let sys = run(&[0x6001, 0x6181, 0x801E]);
+    assert_eq!(sys.v_registers[0], 0x02);
+    assert_eq!(sys.v_registers[0xF], 1);
+}
+
+#[test]
+fn shifts_use_vx_without_quirk() {
+    let mut sys = load(Variant::SuperChip, &[0x6081, 0x6102, 0x8016]);
+    steps(&mut sys, 3);
+    assert_eq!(sys.v_registers[0], 0x40);
+    assert_eq!(sys.v_registers[0xF], 1);
+    let mut sys = load(Variant::SuperChip, &[0x6041, 0x6181, 0x801E]);
+    steps(&mut sys, 3);
+    assert_eq!(sys.v_registers[0], 0x82);
+    assert_eq!(sys.v_registers[0xF], 0);
+}
+
+#[test]
+fn flag_wins_when_vf_is_the_destination() {
+    // The result is written first, then overwritten by the flag
+    let sys = run(&[0x6FFF, 0x6102, 0x8F14]);
+    assert_eq!(sys.v_registers[0xF], 1);
+    let sys = run(&[0x6F05, 0x6103, 0x8F15]);
+    assert_eq!(sys.v_registers[0xF], 1);
+    let sys = run(&[0x6F03, 0x6105, 0x8F15]);
+    assert_eq!(sys.v_registers[0xF], 0);
+    let sys = run(&[0x6F03, 0x6105, 0x8F17]);
+    assert_eq!(sys.v_registers[0xF], 1);
+    let sys = run(&[0x6F02, 0x8FF6]);
+    assert_eq!(sys.v_registers[0xF], 0);
+    let sys = run(&[0x6F81, 0x8FFE]);
+    assert_eq!(sys.v_registers[0xF], 1);
+}
+
+#[test]
+fn vf_as_source_is_read_before_the_flag() {
+    let sys = run(&[0x60FF, 0x6F01, 0x80F4]);
+    assert_eq!(sys.v_registers[0], 0);
+    assert_eq!(sys.v_registers[0xF], 1);
+    let sys = run(&[0x6001, 0x6F02, 0x80F5]);
+    assert_eq!(sys.v_registers[0], 0xFF);
+    assert_eq!(sys.v_registers[0xF], 0);
+}
+
+#[test]
+fn random_is_masked() {
+    let mut sys = load(Variant::Chip8, &[0xC00F, 0xC100]);
+    sys.set_rng(Box::new(XorShiftRng::new(7)));
+    steps(&mut sys, 2);
+    assert_eq!(sys.v_registers[0] & 0xF0, 0);
+    assert_eq!(sys.v_registers[1], 0);
+}
+
+#[test]
+fn random_is_reproducible_with_a_seed() {
+    let program = [0xC0FF, 0xC1FF, 0xC2FF, 0xC3FF];
+    let mut a = load(Variant::Chip8, &program);
+    let mut b = load(Variant::Chip8, &program);
+    a.set_rng(Box::new(XorShiftRng::new(99)));
+    b.set_rng(Box::new(XorShiftRng::new(99)));
+    steps(&mut a, 4);
+    steps(&mut b, 4);
+    assert_eq!(a.v_registers, b.v_registers);
+}
+
+/// A generator that is neither `Clone` nor snapshottable.
+struct Counter(u8);
+
+impl Rng for Counter {
+    fn next_u8(&mut self) -> u8 {
+        self.0 = self.0.wrapping_add(1);
+        self.0
+    }
+}
+
+#[test]
+fn custom_rng_does_not_need_clone() {
+    let mut sys = load(Variant::Chip8, &[0xC0FF, 0xC1FF]);
+    sys.set_rng(Box::new(Counter(0)));
+    let mut copy = sys.clone();
+    steps(&mut sys, 2);
+    assert_eq!(sys.v_registers[..2], [1, 2]);
+    // The clone falls back to the default generator
+    steps(&mut copy, 2);
+    let mut expected = load(Variant::Chip8, &[0xC0FF, 0xC1FF]);
+    expected.set_rng(Box::new(XorShiftRng::default()));
+    steps(&mut expected, 2);
+    assert_eq!(copy.v_registers, expected.v_registers);
+}
+
+// Memory and the I register
+
+#[test]
+fn load_i() {
+    assert_eq!(run(&[0xA123]).i_register, 0x123);
+}
+
+#[test]
+fn long_load_i() {
+    let mut sys = load(Variant::XoChip, &[0xF000, 0xABCD]);
+    sys.step().unwrap();
+    assert_eq!(sys.i_register, 0xABCD);
+    assert_eq!(sys.program_counter, 0x204);
+}
+
+#[test]
+fn long_load_i_is_invalid_before_xochip() {
+    for variant in [Variant::Chip8, Variant::SuperChip] {
+        let mut sys = System::with_variant(variant);
+        let end = sys.memory.len() - 2;
+        sys.memory[end] = 0xF0;
+        sys.program_counter = end as u16;
+        assert_eq!(
+            sys.step(),
+            Err(EmuError::InvalidOpcode {
+                address: end as u16,
+                opcode: 0xF000
+            })
+        );
+    }
+}
+
+#[test]
+fn add_i_leaves_vf_alone() {
+    let sys = run(&[0xAFFF, 0x6002, 0x6F07, 0xF01E]);
+    assert_eq!(sys.i_register, 0x1001);
+    assert_eq!(sys.v_registers[0xF], 7);
+}
+
+#[test]
+fn add_i_wraps_around_16_bits() {
+    let mut sys = load(Variant::XoChip, &[0xF000, 0xFFFF, 0x6002, 0xF01E]);
+    steps(&mut sys, 3);
+    assert_eq!(sys.i_register, 0x0001);
+}
+
+#[test]
+fn load_font() {
+    let sys = run(&[0x601A, 0xF029]);
+    assert_eq!(sys.i_register, sys.font_address() + 0xA * 5);
+}
+
+#[test]
+fn load_big_font() {
+    let mut sys = load(Variant::SuperChip, &[0x6003, 0xF030]);
+    steps(&mut sys, 2);
+    assert_eq!(sys.i_register, sys.font_address() + 80 + 3 * 10);
+}
+
+#[test]
+fn fonts_must_fit_below_the_program() {
+    let mut sys = System::default();
+    assert_eq!(sys.set_font(Font::Chip48, MAX_FONT_ADDRESS), Ok(()));
+    assert_eq!(sys.memory[PROGRAM_START - 1], BIG_FONT[BIG_FONT_SIZE - 1]);
+    assert_eq!(
+        sys.set_font(Font::CosmacVip, MAX_FONT_ADDRESS + 1),
+        Err(EmuError::FontOutOfRange {
+            address: MAX_FONT_ADDRESS + 1
+        })
+    );
+    assert_eq!(sys.font(), Font::Chip48);
+    assert_eq!(sys.font_address(), MAX_FONT_ADDRESS);
+}
+
+#[test]
+fn store_bcd() {
+    for (value, digits) in [
+        (254, [2, 5, 4]),
+        (7, [0, 0, 7]),
+        (100, [1, 0, 0]),
+        (0, [0, 0, 0]),
+    ] {
+        let sys = run(&[0xA300, 0x6000 | value, 0xF033]);
+        assert_eq!(sys.memory[0x300..0x303], digits, "{}", value);
+    }
+}
+
+#[test]
+fn store_bcd_out_of_bounds() {
+    let mut sys = load(Variant::Chip8, &[0xAFFE, 0x60FF, 0xF033]);
+    steps(&mut sys, 2);
+    assert_eq!(
+        sys.step(),
+        Err(EmuError::OutOfBounds {
+            address: MEMORY_SIZE
+        })
+    );
+    assert_eq!(sys.memory[0xFFE..], [0, 0]);
+}
+
+#[test]
+fn store_and_load_regs_increment_i_with_quirk() {
+    let sys = run(&[0x6011, 0x6122, 0x6233, 0xA300, 0xF255]);
+    assert_eq!(sys.memory[0x300..0x304], [0x11, 0x22, 0x33, 0]);
+    assert_eq!(sys.i_register, 0x303);
+    let sys = run(&[0xA300, 0xF265, 0xA300, 0xF165]);
+    assert_eq!(sys.i_register, 0x302);
+}
+
+#[test]
+fn store_and_load_regs_keep_i_without_quirk() {
+    let mut sys = load(
+        Variant::SuperChip,
+        &[0x6011, 0x6122, 0xA300, 0xF155, 0xF265],
+    );
+    sys.memory[0x302] = 0x44;
+    steps(&mut sys, 5);
+    assert_eq!(sys.i_register, 0x300);
+    assert_eq!(sys.v_registers[..3], [0x11, 0x22, 0x44]);
+}
+
+#[test]
+fn store_regs_out_of_bounds() {
+    let mut sys = load(Variant::Chip8, &[0xAFFF, 0xF155]);
+    sys.step().unwrap();
+    assert_eq!(
+        sys.step(),
+        Err(EmuError::OutOfBounds {
+            address: MEMORY_SIZE
+        })
+    );
+    assert_eq!(sys.i_register, 0xFFF);
+}
+
+#[test]
+fn store_and_load_flags() {
+    let mut sys = load(
+        Variant::SuperChip,
+        &[0x6011, 0x6122, 0xF175, 0x6000, 0x6100, 0xF185],
+    );
+    steps(&mut sys, 6);
+    assert_eq!(sys.v_registers[..2], [0x11, 0x22]);
+    assert_eq!(sys.flags[..3], [0x11, 0x22, 0]);
+}
+
+#[test]
+fn store_and_load_range() {
+    let mut sys = load(
+        Variant::XoChip,
+        &[0x6211, 0x6322, 0x6433, 0xA300, 0x5242, 0x5423],
+    );
+    steps(&mut sys, 5);
+    assert_eq!(sys.memory[0x300..0x303], [0x11, 0x22, 0x33]);
+    assert_eq!(sys.i_register, 0x300);
+    // Reversed ranges go from X down to Y
+    sys.step().unwrap();
+    assert_eq!(sys.v_registers[2..5], [0x33, 0x22, 0x11]);
+}
+
+// Timers and keys
+
+#[test]
+fn delay_timer() {
+    let mut sys = run(&[0x6002, 0xF015]);
+    sys.tick_timers();
+    sys.program_counter = 0x200;
+    sys.memory[0x200..0x202].copy_from_slice(&[0xF1, 0x07]);
+    sys.step().unwrap();
+    assert_eq!(sys.v_registers[1], 1);
+    sys.tick_timers();
+    sys.tick_timers();
+    assert_eq!(sys.time_register, 0);
+}
+
+#[test]
+fn sound_timer() {
+    let mut sys = run(&[0x6001, 0xF018]);
+    assert!(sys.is_sound_playing());
+    sys.tick_timers();
+    assert!(!sys.is_sound_playing());
+}
+
+#[test]
+fn wait_key_on_press() {
+    let mut sys = load(Variant::SuperChip, &[0xF30A, 0x6001]);
+    steps(&mut sys, 3);
+    assert!(sys.is_waiting_for_key());
+    assert_eq!(sys.program_counter, 0x202);
+    assert_eq!(sys.v_registers[0], 0);
+    sys.press_key(0xC);
+    assert!(!sys.is_waiting_for_key());
+    assert_eq!(sys.v_registers[3], 0xC);
+}
+
+#[test]
+fn wait_key_on_release() {
+    let mut sys = load(Variant::Chip8, &[0xF30A]);
+    sys.key_wait_trigger = KeyWaitTrigger::Release;
+    sys.step().unwrap();
+    sys.press_key(0xC);
+    sys.press_key(0x4);
+    assert!(sys.is_waiting_for_key());
+    sys.release_key(0x4);
+    assert!(sys.is_waiting_for_key());
+    sys.release_key(0xC);
+    assert!(!sys.is_waiting_for_key());
+    assert_eq!(sys.v_registers[3], 0xC);
+}
+
+#[test]
+fn keys_past_f_are_ignored() {
+    let mut sys = load(Variant::Chip8, &[0xF30A]);
+    sys.step().unwrap();
+    sys.press_key(0x10);
+    sys.press_key(0xFF);
+    assert!(sys.is_waiting_for_key());
+    assert!(!sys.is_key_pressed(0x10));
+    assert_eq!(sys.pressed_keys(), 0);
+    sys.release_key(0x10);
+    assert!(sys.is_waiting_for_key());
+}
+
+// Display
+
+/// Puts `bytes` at 0x300 for sprites to draw from.
+fn with_sprite(mut sys: System, bytes: &[u8]) -> System {
+    sys.memory[0x300..0x300 + bytes.len()].copy_from_slice(bytes);
+    sys
+}
+
+#[test]
+fn draw_and_collide() {
+    let mut sys = with_sprite(
+        load(Variant::Chip8, &[0x6002, 0x6103, 0xA300, 0xD012, 0xD012]),
+        &[0xC0, 0x80],
+    );
+    steps(&mut sys, 4);
+    assert_eq!(lit(&sys), [(2, 3), (3, 3), (2, 4)]);
+    assert_eq!(sys.v_registers[0xF], 0);
+    sys.tick_timers();
+    sys.step().unwrap();
+    assert!(lit(&sys).is_empty());
+    assert_eq!(sys.v_registers[0xF], 1);
+}
+
+#[test]
+fn draw_waits_for_vblank_with_quirk() {
+    let mut sys = with_sprite(load(Variant::Chip8, &[0xA300, 0xD011, 0x6001]), &[0x80]);
+    steps(&mut sys, 3);
+    assert!(sys.is_waiting_for_vblank());
+    assert_eq!(sys.program_counter, 0x204);
+    sys.tick_timers();
+    sys.step().unwrap();
+    assert_eq!(sys.v_registers[0], 1);
+}
+
+#[test]
+fn draw_wraps_the_origin() {
+    let mut sys = with_sprite(
+        load(Variant::Chip8, &[0x6042, 0x6121, 0xA300, 0xD011]),
+        &[0x80],
+    );
+    steps(&mut sys, 4);
+    assert_eq!(lit(&sys), [(2, 1)]);
+}
+
+#[test]
+fn draw_clips_at_the_edges_with_quirk() {
+    let mut sys = with_sprite(
+        load(Variant::Chip8, &[0x603E, 0x611F, 0xA300, 0xD012]),
+        &[0xFF, 0xFF],
+    );
+    steps(&mut sys, 4);
+    assert_eq!(lit(&sys), [(62, 31), (63, 31)]);
+}
+
+#[test]
+fn draw_wraps_at_the_edges_without_quirk() {
+    let mut sys = with_sprite(
+        load(Variant::XoChip, &[0x603E, 0x611F, 0xA300, 0xD012]),
+        &[0xC1, 0x80],
+    );
+    steps(&mut sys, 4);
+    assert_eq!(lit(&sys), [(62, 0), (5, 31), (62, 31), (63, 31)]);
+}
+
+#[test]
+fn draw_zero_rows_on_chip8() {
+    let mut sys = with_sprite(load(Variant::Chip8, &[0xA300, 0xD010]), &[0xFF; 32]);
+    steps(&mut sys, 2);
+    assert!(lit(&sys).is_empty());
+}
+
+#[test]
+fn draw_big_sprite() {
+    let mut sys = with_sprite(
+        load(Variant::SuperChip, &[0x00FF, 0xA300, 0xD010]),
+        &[0xFF; 32],
+    );
+    steps(&mut sys, 3);
+    assert!(sys.is_hires());
+    assert_eq!(lit(&sys).len(), 256);
+    assert!(sys.display().is_lit(15, 15, 0));
+    assert!(!sys.display().is_lit(16, 0, 0));
+}
+
+#[test]
+fn hires_collision_counts_rows_and_clipped_rows() {
+    let mut sys = with_sprite(
+        load(
+            Variant::SuperChip,
+            &[0x00FF, 0x6100, 0xA300, 0xD010, 0xD010, 0x613C, 0xD010],
+        ),
+        &[0xFF; 32],
+    );
+    steps(&mut sys, 4);
+    assert_eq!(sys.v_registers[0xF], 0);
+    sys.step().unwrap();
+    assert_eq!(sys.v_registers[0xF], 16);
+    // Drawn at row 60, the last 12 rows fall off the bottom
+    steps(&mut sys, 2);
+    assert_eq!(sys.v_registers[0xF], 12);
+}
+
+#[test]
+fn lores_collision_is_a_flag_on_superchip() {
+    let mut sys = with_sprite(
+        load(Variant::SuperChip, &[0xA300, 0xD012, 0xD012]),
+        &[0xFF, 0xFF],
+    );
+    steps(&mut sys, 3);
+    assert_eq!(sys.v_registers[0xF], 1);
+}
+
+#[test]
+fn draw_on_selected_planes() {
+    let mut sys = with_sprite(
+        load(
+            Variant::XoChip,
+            &[0xA300, 0xF201, 0xD011, 0xF301, 0x6108, 0xD011],
+        ),
+        &[0x80, 0xC0, 0x40],
+    );
+    steps(&mut sys, 3);
+    assert_eq!(sys.display().get(0, 0), 0b10);
+    // With both planes selected, the second plane's sprite follows the first
+    steps(&mut sys, 3);
+    assert_eq!(sys.display().get(0, 8), 0b11);
+    assert_eq!(sys.display().get(1, 8), 0b10);
+}
+
+#[test]
+fn clear_screen_clears_selected_planes() {
+    let mut sys = load(Variant::XoChip, &[0xF101, 0x00E0]);
+    sys.display.xor_row(0, 0, 0, 1, 1, false);
+    sys.display.xor_row(1, 1, 0, 1, 1, false);
+    steps(&mut sys, 2);
+    assert_eq!(lit(&sys), [(1, 0)]);
+}
+
+#[test]
+fn resolution_switches_clear_the_screen() {
+    let mut sys = load(Variant::SuperChip, &[0x00FF, 0x00FE]);
+    sys.display.xor_row(0, 0, 0, 1, 1, false);
+    sys.step().unwrap();
+    assert!(sys.is_hires());
+    assert!(lit(&sys).is_empty());
+    sys.display.xor_row(0, 0, 0, 1, 1, false);
+    sys.step().unwrap();
+    assert!(!sys.is_hires());
+    assert!(lit(&sys).is_empty());
+}
+
+#[test]
+fn scroll() {
+    let mut sys = load(Variant::XoChip, &[0x00C2, 0x00FB, 0x00D1, 0x00FC]);
+    sys.display.xor_row(0, 0, 0, 1, 1, false);
+    sys.step().unwrap();
+    assert_eq!(lit(&sys), [(0, 2)]);
+    sys.step().unwrap();
+    assert_eq!(lit(&sys), [(4, 2)]);
+    sys.step().unwrap();
+    assert_eq!(lit(&sys), [(4, 1)]);
+    sys.step().unwrap();
+    assert_eq!(lit(&sys), [(0, 1)]);
+}
+
+#[test]
+fn scroll_drops_pixels_off_the_edge() {
+    let mut sys = load(Variant::SuperChip, &[0x00FC, 0x00CF]);
+    sys.display.xor_row(0, 1, 20, 1, 1, false);
+    steps(&mut sys, 2);
+    assert!(lit(&sys).is_empty());
+}
+
+// XO-CHIP audio
+
+#[test]
+fn load_audio_and_pitch() {
+    let mut sys = with_sprite(
+        load(Variant::XoChip, &[0xA300, 0xF002, 0x6070, 0xF03A]),
+        &[0xAA; 16],
+    );
+    steps(&mut sys, 4);
+    assert_eq!(sys.audio_pattern, [0xAA; AUDIO_PATTERN_SIZE]);
+    assert_eq!(sys.pitch, 0x70);
+    assert!(sys.audio_sample_rate() > 4000.);
+}
@@ -0,0 +1,127 @@
+use crate::{Instruction, System};
+
+/// A piece of machine state that an instruction can change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Register {
+    V(u8),
+    I,
+    ProgramCounter,
+    StackPointer,
+    DelayTimer,
+    SoundTimer,
+}
+
+/// The value of a register before and after an instruction ran.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegisterChange {
+    pub register: Register,
+    pub old: u16,
+    pub new: u16,
+}
+
+/// Everything that happened during a single executed instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TraceEvent {
+    /// Address the instruction was fetched from.
+    pub address: u16,
+    pub opcode: u16,
+    pub instruction: Instruction,
+    pub changes: Vec<RegisterChange>,
+}
+
+/// Observer notified by `System` as it runs. Closures taking a `&TraceEvent` can be
+/// used directly.
+pub trait Tracer: Send {
+    /// Called after every successfully executed instruction.
+    fn on_step(&mut self, event: &TraceEvent);
+
+    /// Called after a ROM of `size` bytes was loaded.
+    fn on_load(&mut self, _size: usize) {}
+}
+
+impl<F: FnMut(&TraceEvent) + Send> Tracer for F {
+    fn on_step(&mut self, event: &TraceEvent) {
+        self(event)
+    }
+}
+
+/// Forwards events to the `log` crate at trace level.
+#[cfg(feature = "log")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogTracer;
+
+#[cfg(feature = "log")]
+impl Tracer for LogTracer {
+    fn on_step(&mut self, event: &TraceEvent) {
+        log::trace!(
+            "{:#05x}: {:04x} {:<16} {:?}",
+            event.address,
+            event.opcode,
+            event.instruction.to_string(),
+            event.changes
+        );
+    }
+
+    fn on_load(&mut self, size: usize) {
+        log::trace!("loaded {} byte ROM", size);
+    }
+}
+
+/// Register values captured before an instruction, to diff against afterwards.
+pub(crate) struct RegisterSnapshot {
+    values: [u16; 21],
+}
+
+impl RegisterSnapshot {
+    const REGISTERS: [Register; 21] = [
+        Register::V(0x0),
+        Register::V(0x1),
+        Register::V(0x2),
+        Register::V(0x3),
+        Register::V(0x4),
+        Register::V(0x5),
+        Register::V(0x6),
+        Register::V(0x7),
+        Register::V(0x8),
+        Register::V(0x9),
+        Register::V(0xA),
+        Register::V(0xB),
+        Register::V(0xC),
+        Register::V(0xD),
+        Register::V(0xE),
+        Register::V(0xF),
+        Register::I,
+        Register::ProgramCounter,
+        Register::StackPointer,
+        Register::DelayTimer,
+        Register::SoundTimer,
+    ];
+
+    pub(crate) fn capture(sys: &System) -> Self {
+        let mut values = [0; 21];
+        for (value, register) in values.iter_mut().zip(Self::REGISTERS) {
+            *value = match register {
+                Register::V(index) => sys.v_registers[index as usize] as u16,
+                Register::I => sys.i_register,
+                Register::ProgramCounter => sys.program_counter,
+                Register::StackPointer => sys.stack_pointer as u16,
+                Register::DelayTimer => sys.time_register as u16,
+                Register::SoundTimer => sys.sound_register as u16,
+            };
+        }
+        Self { values }
+    }
+
+    pub(crate) fn changes(&self, after: &Self) -> Vec<RegisterChange> {
+        Self::REGISTERS
+            .iter()
+            .zip(self.values.iter().zip(after.values))
+            .filter(|(_, (old, new))| **old != *new)
+            .map(|(register, (old, new))| RegisterChange {
+                register: *register,
+                old: *old,
+                new,
+            })
+            .collect()
+    }
+}
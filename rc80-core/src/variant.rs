@@ -0,0 +1,66 @@
+use crate::{Font, Instruction, Quirks, MEMORY_SIZE, XO_MEMORY_SIZE};
+
+/// The CHIP-8 dialect a program is written for, which decides the instructions
+/// available to it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Variant {
+    /// The original COSMAC VIP instruction set.
+    #[default]
+    Chip8,
+    /// SUPER-CHIP 1.1, adding a 128x64 mode, scrolling and 16x16 sprites.
+    SuperChip,
+    /// XO-CHIP, extending SUPER-CHIP with 64K of memory, two bitplanes and audio.
+    XoChip,
+}
+
+impl Variant {
+    /// Whether `instruction` exists in this variant.
+    pub fn supports(self, instruction: Instruction) -> bool {
+        match instruction {
+            Instruction::ScrollDown(_)
+            | Instruction::ScrollRight
+            | Instruction::ScrollLeft
+            | Instruction::Exit
+            | Instruction::LowRes
+            | Instruction::HighRes
+            | Instruction::LoadBigFont { .. }
+            | Instruction::StoreFlags { .. }
+            | Instruction::LoadFlags { .. } => self != Variant::Chip8,
+            Instruction::ScrollUp(_)
+            | Instruction::StoreRange { .. }
+            | Instruction::LoadRange { .. }
+            | Instruction::LoadLongI(_)
+            | Instruction::SelectPlanes(_)
+            | Instruction::LoadAudio
+            | Instruction::SetPitch { .. } => self == Variant::XoChip,
+            _ => true,
+        }
+    }
+
+    /// The quirks of the reference interpreter for this variant.
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Variant::Chip8 => Quirks::cosmac_vip(),
+            Variant::SuperChip => Quirks::super_chip(),
+            Variant::XoChip => Quirks::xo_chip(),
+        }
+    }
+
+    /// The small font of the reference interpreter for this variant.
+    pub fn font(self) -> Font {
+        match self {
+            Variant::Chip8 => Font::CosmacVip,
+            Variant::SuperChip => Font::Chip48,
+            Variant::XoChip => Font::CosmacVip,
+        }
+    }
+
+    /// Size of the addressable memory in bytes.
+    pub fn memory_size(self) -> usize {
+        match self {
+            Variant::Chip8 | Variant::SuperChip => MEMORY_SIZE,
+            Variant::XoChip => XO_MEMORY_SIZE,
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Property tests checking the arithmetic instructions against a small reference model
+//! written straight from the spec, over random register states.
+
+use proptest::prelude::*;
+use rc80_core::{Quirks, System, MEMORY_SIZE, PROGRAM_START, REGISTER_AMOUNT};
+
+/// The state the ALU instructions read and write.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Alu {
+    v: [u8; REGISTER_AMOUNT],
+    i: u16,
+    /// The three bytes at I, which FX33 writes.
+    bcd: [u8; 3],
+}
+
+/// Executes `opcode` on the reference model. Operands are read before anything is
+/// written, and VF is written last so the flag wins when VF is the destination.
+fn reference(opcode: u16, alu: &Alu, quirks: &Quirks) -> Alu {
+    let mut out = alu.clone();
+    let x = (opcode >> 8 & 0xF) as usize;
+    let y = (opcode >> 4 & 0xF) as usize;
+    let vx = alu.v[x] as u32;
+    let vy = alu.v[y] as u32;
+    let nn = (opcode & 0xFF) as u32;
+    let shifted = if quirks.shift_uses_vy { vy } else { vx };
+    let (result, flag) = match (opcode >> 12, opcode & 0xF) {
+        (0x7, _) => ((vx + nn) % 256, None),
+        (0x8, 0x0) => (vy, None),
+        (0x8, 0x1) => (vx | vy, quirks.logic_resets_vf.then_some(0)),
+        (0x8, 0x2) => (vx & vy, quirks.logic_resets_vf.then_some(0)),
+        (0x8, 0x3) => (vx ^ vy, quirks.logic_resets_vf.then_some(0)),
+        (0x8, 0x4) => ((vx + vy) % 256, Some((vx + vy > 255) as u32)),
+        (0x8, 0x5) => ((vx + 256 - vy) % 256, Some((vx >= vy) as u32)),
+        (0x8, 0x6) => (shifted / 2, Some(shifted % 2)),
+        (0x8, 0x7) => ((vy + 256 - vx) % 256, Some((vy >= vx) as u32)),
+        (0x8, 0xE) => (shifted * 2 % 256, Some(shifted / 128)),
+        (0xF, _) if opcode & 0xFF == 0x1E => {
+            out.i = ((alu.i as u32 + vx) % 0x10000) as u16;
+            return out;
+        }
+        (0xF, _) if opcode & 0xFF == 0x33 => {
+            // Count out the digits rather than dividing, to stay independent
+            let mut rest = vx;
+            for (digit, weight) in out.bcd.iter_mut().zip([100, 10, 1]) {
+                *digit = 0;
+                while rest >= weight {
+                    rest -= weight;
+                    *digit += 1;
+                }
+            }
+            return out;
+        }
+        _ => unreachable!("no reference for {:04x}", opcode),
+    };
+    out.v[x] = result as u8;
+    if let Some(flag) = flag {
+        out.v[0xF] = flag as u8;
+    }
+    out
+}
+
+/// Executes `opcode` on a fresh system with the given state.
+fn emulate(opcode: u16, alu: &Alu, quirks: &Quirks) -> Alu {
+    let mut sys = System::default();
+    sys.quirks = *quirks;
+    sys.v_registers = alu.v;
+    sys.i_register = alu.i;
+    let bcd_at = alu.i as usize;
+    if bcd_at + 3 <= MEMORY_SIZE {
+        sys.memory[bcd_at..bcd_at + 3].copy_from_slice(&alu.bcd);
+    }
+    sys.memory[PROGRAM_START..PROGRAM_START + 2].copy_from_slice(&opcode.to_be_bytes());
+    sys.step().unwrap();
+    assert_eq!(sys.program_counter as usize, PROGRAM_START + 2);
+    let mut bcd = alu.bcd;
+    if bcd_at + 3 <= MEMORY_SIZE {
+        bcd.copy_from_slice(&sys.memory[bcd_at..bcd_at + 3]);
+    }
+    Alu {
+        v: sys.v_registers,
+        i: sys.i_register,
+        bcd,
+    }
+}
+
+fn quirks() -> impl Strategy<Value = Quirks> {
+    (any::<bool>(), any::<bool>()).prop_map(|(shift_uses_vy, logic_resets_vf)| Quirks {
+        shift_uses_vy,
+        logic_resets_vf,
+        ..Quirks::default()
+    })
+}
+
+fn alu(i: impl Strategy<Value = u16>) -> impl Strategy<Value = Alu> {
+    // The bytes at I can't share memory with the opcode under test
+    let i = i.prop_filter("I overlaps the opcode", |i| {
+        !(PROGRAM_START - 2..PROGRAM_START + 2).contains(&(*i as usize))
+    });
+    (any::<[u8; REGISTER_AMOUNT]>(), i, any::<[u8; 3]>()).prop_map(|(v, i, bcd)| Alu { v, i, bcd })
+}
+
+fn register() -> impl Strategy<Value = u16> {
+    0..REGISTER_AMOUNT as u16
+}
+
+proptest! {
+    #[test]
+    fn register_ops(
+        alu in alu(any::<u16>()),
+        quirks in quirks(),
+        x in register(),
+        y in register(),
+        n in prop::sample::select(vec![0x0, 0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0xE]),
+    ) {
+        let opcode = 0x8000 | x << 8 | y << 4 | n;
+        prop_assert_eq!(emulate(opcode, &alu, &quirks), reference(opcode, &alu, &quirks));
+    }
+
+    #[test]
+    fn add_byte(alu in alu(any::<u16>()), x in register(), nn in any::<u8>()) {
+        let opcode = 0x7000 | x << 8 | nn as u16;
+        let quirks = Quirks::default();
+        prop_assert_eq!(emulate(opcode, &alu, &quirks), reference(opcode, &alu, &quirks));
+    }
+
+    #[test]
+    fn add_i(alu in alu(any::<u16>()), x in register()) {
+        let opcode = 0xF01E | x << 8;
+        let quirks = Quirks::default();
+        prop_assert_eq!(emulate(opcode, &alu, &quirks), reference(opcode, &alu, &quirks));
+    }
+
+    #[test]
+    fn store_bcd(alu in alu(0..=(MEMORY_SIZE - 3) as u16), x in register()) {
+        let opcode = 0xF033 | x << 8;
+        let quirks = Quirks::default();
+        prop_assert_eq!(emulate(opcode, &alu, &quirks), reference(opcode, &alu, &quirks));
+    }
+}
@@ -0,0 +1,302 @@
+//! Runs the Timendus CHIP-8 test suite (https://github.com/Timendus/chip8-test-suite)
+//! headlessly and compares the final screens against golden images.
+//!
+//! The suite is GPL licensed, so its ROMs are not part of this repository and these
+//! tests are ignored by default. Download the ROMs from the suite's `bin` directory into
+//! `rc80-core/tests/roms`, or point `RC80_TEST_ROMS` at them, then run
+//! `cargo test -p rc80-core --test conformance -- --ignored`. A missing ROM or golden
+//! image fails the test rather than passing silently. A few small ROMs written out in
+//! this file go through the same checks and always run.
+//!
+//! Golden images live in `rc80-core/tests/golden`, one text file per ROM and platform
+//! with a character per pixel. Run with `RC80_BLESS=1` to write them from the current
+//! output, after checking the screens show the suite's pass marks.
+
+use std::path::PathBuf;
+
+use rc80_core::{KeyWaitTrigger, System, Variant};
+
+/// Address the suite reads to skip its menus, picking a platform or a test.
+const MENU_CHOICE: usize = 0x1FF;
+
+/// Platform profiles each ROM is run under, with their golden image suffix.
+const PROFILES: [(Variant, &str); 3] = [
+    (Variant::Chip8, "chip8"),
+    (Variant::SuperChip, "superchip"),
+    (Variant::XoChip, "xochip"),
+];
+
+struct Case {
+    rom: &'static str,
+    frames: u32,
+    /// Value poked at `MENU_CHOICE` before running, if the ROM has a menu.
+    menu_choice: Option<fn(Variant) -> u8>,
+    /// Keys held during a range of frames, as `(first frame, key, frames)`.
+    presses: &'static [(u32, u8, u32)],
+}
+
+impl Case {
+    const fn new(rom: &'static str, frames: u32) -> Self {
+        Self {
+            rom,
+            frames,
+            menu_choice: None,
+            presses: &[],
+        }
+    }
+}
+
+fn rom_dir() -> PathBuf {
+    std::env::var_os("RC80_TEST_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/roms"))
+}
+
+fn golden_path(rom: &str, profile: &str) -> PathBuf {
+    let name = rom.trim_end_matches(".ch8");
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}-{}.txt", name, profile))
+}
+
+/// Renders the screen with `.` for unlit pixels, `#` for the first plane, `o` for the
+/// second and `@` for both.
+fn screen_text(sys: &System) -> String {
+    let display = sys.display();
+    let mut text = String::new();
+    for y in 0..display.height() {
+        text.extend((0..display.width()).map(|x| ['.', '#', 'o', '@'][display.get(x, y) as usize]));
+        text.push('\n');
+    }
+    text
+}
+
+fn run(case: &Case, variant: Variant, rom: &[u8]) -> System {
+    let mut sys = System::with_seed(0);
+    sys.set_variant(variant);
+    if variant == Variant::Chip8 {
+        // The COSMAC VIP resumes FX0A on release, which the keypad test checks
+        sys.key_wait_trigger = KeyWaitTrigger::Release;
+    }
+    sys.load(rom).unwrap();
+    if let Some(choice) = case.menu_choice {
+        sys.memory[MENU_CHOICE] = choice(variant);
+    }
+    for frame in 0..case.frames {
+        let keys = case
+            .presses
+            .iter()
+            .filter(|(first, _, frames)| (*first..first + frames).contains(&frame))
+            .fold(0, |keys, (_, key, _)| keys | 1 << key);
+        sys.set_pressed_keys(keys);
+        sys.run_frame()
+            .unwrap_or_else(|err| panic!("{} as {:?}: {}", case.rom, variant, err));
+    }
+    sys
+}
+
+/// Runs `case` from the suite under every profile and checks the screens against the
+/// golden images. Returns the final systems.
+fn check(case: &Case) -> Vec<System> {
+    let path = rom_dir().join(case.rom);
+    let rom = std::fs::read(&path).unwrap_or_else(|err| {
+        panic!(
+            "cannot read {}: {}, see the module documentation for where to get the ROMs",
+            path.display(),
+            err
+        )
+    });
+    check_rom(case, &rom)
+}
+
+/// Like `check`, with the ROM given directly.
+fn check_rom(case: &Case, rom: &[u8]) -> Vec<System> {
+    let bless = std::env::var_os("RC80_BLESS").is_some();
+    let mut mismatches = vec![];
+    let mut systems = vec![];
+    for (variant, profile) in PROFILES {
+        let sys = run(case, variant, rom);
+        let screen = screen_text(&sys);
+        let golden = golden_path(case.rom, profile);
+        if bless {
+            std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+            std::fs::write(&golden, &screen).unwrap();
+        } else {
+            let expected = std::fs::read_to_string(&golden).unwrap_or_else(|_| {
+                panic!(
+                    "no golden image at {}, run with RC80_BLESS=1 to create it",
+                    golden.display()
+                )
+            });
+            if screen != expected {
+                mismatches.push(format!("{}:\n{}", golden.display(), screen));
+            }
+        }
+        systems.push(sys);
+    }
+    assert!(
+        mismatches.is_empty(),
+        "{} differs from the golden image, got\n{}",
+        case.rom,
+        mismatches.join("\n")
+    );
+    systems
+}
+
+/// Draws the 16 font digits in two rows:
+///
+/// ```text
+/// 0x200  6000  V0 = 0        digit
+/// 0x202  6100  V1 = 0        x
+/// 0x204  6200  V2 = 0        y
+/// 0x206  F029  I = digit V0
+/// 0x208  D125  draw at (V1, V2)
+/// 0x20A  7105  V1 += 5
+/// 0x20C  7001  V0 += 1
+/// 0x20E  4008  skip unless V0 == 8
+/// 0x210  2220  call 0x220
+/// 0x212  4010  skip unless V0 == 16
+/// 0x214  1214  jump 0x214
+/// 0x216  1206  jump 0x206
+/// 0x220  6100  V1 = 0        next row
+/// 0x222  7206  V2 += 6
+/// 0x224  00EE  return
+/// ```
+const FONT_DIGITS: [u8; 38] = [
+    0x60, 0x00, 0x61, 0x00, 0x62, 0x00, 0xF0, 0x29, 0xD1, 0x25, 0x71, 0x05, 0x70, 0x01, 0x40, 0x08,
+    0x22, 0x20, 0x40, 0x10, 0x12, 0x14, 0x12, 0x06, 0, 0, 0, 0, 0, 0, 0, 0, 0x61, 0x00, 0x72, 0x06,
+    0x00, 0xEE,
+];
+
+/// Draws a box across the bottom right corner, where platforms either clip or wrap it,
+/// then two overlapping boxes, and shows the collision flag as a digit:
+///
+/// ```text
+/// 0x200  A220  I = 0x220     box
+/// 0x202  603C  V0 = 60
+/// 0x204  611E  V1 = 30
+/// 0x206  D014  draw at (V0, V1)
+/// 0x208  6002  V0 = 2
+/// 0x20A  6102  V1 = 2
+/// 0x20C  D014  draw at (V0, V1)
+/// 0x20E  6004  V0 = 4
+/// 0x210  6103  V1 = 3
+/// 0x212  D014  draw at (V0, V1), colliding
+/// 0x214  FF29  I = digit VF
+/// 0x216  6014  V0 = 20
+/// 0x218  610A  V1 = 10
+/// 0x21A  D015  draw at (V0, V1)
+/// 0x21C  121C  jump 0x21C
+/// 0x220  F0 90 90 F0
+/// ```
+const EDGES_AND_COLLISIONS: [u8; 36] = [
+    0xA2, 0x20, 0x60, 0x3C, 0x61, 0x1E, 0xD0, 0x14, 0x60, 0x02, 0x61, 0x02, 0xD0, 0x14, 0x60, 0x04,
+    0x61, 0x03, 0xD0, 0x14, 0xFF, 0x29, 0x60, 0x14, 0x61, 0x0A, 0xD0, 0x15, 0x12, 0x1C, 0, 0, 0xF0,
+    0x90, 0x90, 0xF0,
+];
+
+#[test]
+fn font_digits() {
+    // The display wait lets through one draw per frame
+    check_rom(&Case::new("font-digits", 30), &FONT_DIGITS);
+}
+
+#[test]
+fn edges_and_collisions() {
+    let systems = check_rom(
+        &Case::new("edges-and-collisions", 10),
+        &EDGES_AND_COLLISIONS,
+    );
+    for sys in systems {
+        assert_eq!(
+            sys.v_registers[0xF], 0,
+            "the digit collided as {:?}",
+            sys.variant
+        );
+    }
+}
+
+#[test]
+fn screens_mark_each_plane() {
+    // Draws a pixel on the first plane, the second and both, in the first three columns.
+    // With both planes selected, each plane takes its own row of the sprite
+    let rom = [
+        0xA2, 0x12, 0xF1, 0x01, 0xD0, 0x11, 0x70, 0x01, 0xF2, 0x01, 0xD0, 0x11, 0x70, 0x01, 0xF3,
+        0x01, 0xD0, 0x11, 0x80, 0x80,
+    ];
+    let mut sys = System::with_variant(Variant::XoChip);
+    sys.quirks.display_wait = false;
+    sys.load(&rom).unwrap();
+    for _ in 0..9 {
+        sys.step().unwrap();
+    }
+    let screen = screen_text(&sys);
+    let lines: Vec<&str> = screen.lines().collect();
+    assert_eq!(lines.len(), 32);
+    assert!(lines.iter().all(|line| line.len() == 64));
+    assert!(lines[0].starts_with("#o@."));
+    assert!(lines[1..]
+        .iter()
+        .all(|line| !line.contains(['#', 'o', '@'])));
+}
+
+#[test]
+#[ignore = "needs the Timendus test suite ROMs"]
+fn chip8_logo() {
+    check(&Case::new("1-chip8-logo.ch8", 60));
+}
+
+#[test]
+#[ignore = "needs the Timendus test suite ROMs"]
+fn ibm_logo() {
+    check(&Case::new("2-ibm-logo.ch8", 60));
+}
+
+#[test]
+#[ignore = "needs the Timendus test suite ROMs"]
+fn corax_plus() {
+    check(&Case::new("3-corax+.ch8", 60));
+}
+
+#[test]
+#[ignore = "needs the Timendus test suite ROMs"]
+fn flags() {
+    check(&Case::new("4-flags.ch8", 120));
+}
+
+#[test]
+#[ignore = "needs the Timendus test suite ROMs"]
+fn quirks() {
+    check(&Case {
+        menu_choice: Some(|variant| match variant {
+            Variant::Chip8 => 1,
+            Variant::SuperChip => 2,
+            Variant::XoChip => 3,
+        }),
+        ..Case::new("5-quirks.ch8", 600)
+    });
+}
+
+#[test]
+#[ignore = "needs the Timendus test suite ROMs"]
+fn keypad_fx0a() {
+    // Test 3 waits on FX0A, then reports whether it resumed on press or release
+    check(&Case {
+        menu_choice: Some(|_| 3),
+        presses: &[(30, 0x5, 10)],
+        ..Case::new("6-keypad.ch8", 90)
+    });
+}
+
+#[test]
+#[ignore = "needs the Timendus test suite ROMs"]
+fn beep() {
+    // The ROM beeps for as long as B is held
+    let systems = check(&Case {
+        presses: &[(30, 0xB, 60)],
+        ..Case::new("7-beep.ch8", 60)
+    });
+    for sys in systems {
+        assert!(sys.is_sound_playing(), "no beep as {:?}", sys.variant);
+    }
+}
@@ -0,0 +1,310 @@
+//! Breakpoints, conditions and run control of the debugger.
+
+use rc80_core::debugger::{Location, OpcodePattern};
+use rc80_core::{
+    Breakpoint, BreakpointError, Debugger, EmuError, Expr, ExprError, StopReason, System, Variant,
+    PROGRAM_START,
+};
+
+/// Counts V0 up forever, drawing a sprite every iteration:
+///
+/// ```text
+/// 0x200  6000  V0 = 0
+/// 0x202  A20A  I = 0x20A
+/// 0x204  7001  V0 += 1
+/// 0x206  D011  draw 8x1 at (V0, V1)
+/// 0x208  1204  jump 0x204
+/// 0x20A  80    sprite
+/// ```
+const COUNTER: [u8; 11] = [
+    0x60, 0x00, 0xA2, 0x0A, 0x70, 0x01, 0xD0, 0x11, 0x12, 0x04, 0x80,
+];
+
+fn system(rom: &[u8]) -> System {
+    let mut sys = System::with_seed(0);
+    sys.load(rom).unwrap();
+    sys
+}
+
+fn eval(source: &str, sys: &System) -> i64 {
+    Expr::parse(source).unwrap().eval(sys)
+}
+
+#[test]
+fn expressions_read_registers_and_memory() {
+    let mut sys = system(&COUNTER);
+    sys.v_registers[3] = 0x10;
+    sys.v_registers[0xF] = 1;
+    sys.i_register = PROGRAM_START as u16;
+    sys.time_register = 5;
+    assert_eq!(eval("V3", &sys), 0x10);
+    assert_eq!(eval("vf + dt", &sys), 6);
+    assert_eq!(eval("[I]", &sys), 0x60);
+    assert_eq!(eval("[I + 2]", &sys), 0xA2);
+    assert_eq!(eval("[0xFFFF]", &sys), 0);
+    assert_eq!(eval("PC == 0x200 && SP == 0", &sys), 1);
+    assert_eq!(eval("V3 == 0x10 && [I] != 0", &sys), 1);
+    assert_eq!(eval("V3 == 0x10 && [I + 1] != 0", &sys), 0);
+}
+
+#[test]
+fn expressions_follow_precedence() {
+    let sys = System::default();
+    assert_eq!(eval("1 + 2 == 3", &sys), 1);
+    assert_eq!(eval("6 & 3 == 2", &sys), 1);
+    assert_eq!(eval("1 | 6 & 3", &sys), 3);
+    assert_eq!(eval("0 || 1 && 0", &sys), 0);
+    assert_eq!(eval("(0 || 1) && 2", &sys), 1);
+    assert_eq!(eval("5 - 3 - 1", &sys), 1);
+    assert_eq!(eval("!0 + -1", &sys), 0);
+    assert_eq!(eval("2 <= 2 && 3 > 2", &sys), 1);
+}
+
+#[test]
+fn expression_errors_point_at_the_problem() {
+    assert_eq!(
+        Expr::parse("V3 = 1"),
+        Err(ExprError::UnexpectedChar { position: 3 })
+    );
+    assert_eq!(
+        Expr::parse("VG"),
+        Err(ExprError::UnknownName { position: 0 })
+    );
+    assert_eq!(
+        Expr::parse("1 + 0xZ"),
+        Err(ExprError::InvalidNumber { position: 4 })
+    );
+    assert_eq!(Expr::parse("[I"), Err(ExprError::UnexpectedEnd));
+    assert_eq!(
+        Expr::parse("V0 V1"),
+        Err(ExprError::UnexpectedToken { position: 3 })
+    );
+    assert_eq!(
+        Expr::parse("(V0 == 1))"),
+        Err(ExprError::UnexpectedToken { position: 9 })
+    );
+}
+
+#[test]
+fn opcode_patterns_match_free_nibbles() {
+    let draw = OpcodePattern::parse("DXYN").unwrap();
+    assert!(draw.matches(0xD011));
+    assert!(draw.matches(0xDFFF));
+    assert!(!draw.matches(0xC011));
+    assert_eq!(draw.to_string(), "D???");
+    let add_i = OpcodePattern::parse("fx1e").unwrap();
+    assert!(add_i.matches(0xF31E));
+    assert!(!add_i.matches(0xF329));
+    assert_eq!(OpcodePattern::parse("DXY"), None);
+}
+
+#[test]
+fn breakpoints_parse_and_print() {
+    let breakpoint = Breakpoint::parse("0x2a4").unwrap();
+    assert_eq!(breakpoint.location, Location::Address(0x2A4));
+    assert_eq!(breakpoint.condition, None);
+    assert_eq!(breakpoint.to_string(), "0x02a4");
+
+    let breakpoint = Breakpoint::parse("DXYN if V3 == 0x10 && [I] != 0").unwrap();
+    assert_eq!(breakpoint.to_string(), "D??? if V3 == 0x10 && [I] != 0");
+    assert_eq!(Breakpoint::parse(&breakpoint.to_string()), Ok(breakpoint));
+
+    let breakpoint = Breakpoint::parse("if DT == 0").unwrap();
+    assert_eq!(breakpoint.location, Location::Anywhere);
+    assert_eq!(breakpoint.to_string(), "if DT == 0");
+
+    assert_eq!(
+        Breakpoint::parse("0xZZ"),
+        Err(BreakpointError::InvalidLocation)
+    );
+    assert_eq!(
+        Breakpoint::parse("0x200 if"),
+        Err(BreakpointError::InvalidLocation)
+    );
+    assert_eq!(
+        Breakpoint::parse("0x200 if V0 =="),
+        Err(BreakpointError::Condition(ExprError::UnexpectedEnd))
+    );
+}
+
+#[test]
+fn stops_at_address_breakpoints_and_resumes_past_them() {
+    let mut sys = system(&COUNTER);
+    let mut debugger = Debugger::new();
+    let id = debugger.add_breakpoint(Breakpoint::at(0x206));
+    for expected in 1..=3 {
+        assert_eq!(
+            debugger.run(&mut sys, 100),
+            StopReason::Breakpoint { id, address: 0x206 }
+        );
+        assert_eq!(sys.program_counter, 0x206);
+        assert_eq!(sys.v_registers[0], expected);
+    }
+}
+
+#[test]
+fn stops_on_opcode_patterns() {
+    let mut sys = system(&COUNTER);
+    let mut debugger = Debugger::new();
+    let id = debugger.add_breakpoint(Breakpoint::on_opcode(OpcodePattern::parse("DXYN").unwrap()));
+    assert_eq!(
+        debugger.run(&mut sys, 100),
+        StopReason::Breakpoint { id, address: 0x206 }
+    );
+    // The draw hasn't run yet
+    assert_eq!(sys.display().get(1, 0), 0);
+    assert_eq!(debugger.step(&mut sys), StopReason::Stepped);
+    assert_eq!(sys.display().get(1, 0), 1);
+}
+
+#[test]
+fn conditions_gate_breakpoints() {
+    let mut sys = system(&COUNTER);
+    let mut debugger = Debugger::new();
+    let condition = Expr::parse("V0 == 5").unwrap();
+    let id = debugger.add_breakpoint(Breakpoint::at(0x206).with_condition(condition));
+    assert_eq!(
+        debugger.run(&mut sys, 100),
+        StopReason::Breakpoint { id, address: 0x206 }
+    );
+    assert_eq!(sys.v_registers[0], 5);
+
+    let watch = debugger.add_breakpoint(Breakpoint::when(Expr::parse("V0 == 7").unwrap()));
+    assert_eq!(
+        debugger.run(&mut sys, 100),
+        StopReason::Breakpoint {
+            id: watch,
+            address: 0x206
+        }
+    );
+    assert_eq!(sys.v_registers[0], 7);
+}
+
+#[test]
+fn disabled_and_removed_breakpoints_do_not_trigger() {
+    let mut sys = system(&COUNTER);
+    let mut debugger = Debugger::new();
+    let first = debugger.add_breakpoint(Breakpoint::at(0x204));
+    let second = debugger.add_breakpoint(Breakpoint::at(0x206));
+    debugger.breakpoint_mut(first).unwrap().enabled = false;
+    assert_eq!(
+        debugger.run(&mut sys, 100),
+        StopReason::Breakpoint {
+            id: second,
+            address: 0x206
+        }
+    );
+    assert!(debugger.remove_breakpoint(second).is_some());
+    assert!(debugger.remove_breakpoint(second).is_none());
+    assert_eq!(debugger.breakpoints().count(), 1);
+    assert_eq!(debugger.run(&mut sys, 100), StopReason::StepLimit);
+}
+
+#[test]
+fn run_until_stops_at_the_address() {
+    let mut sys = system(&COUNTER);
+    let mut debugger = Debugger::new();
+    assert_eq!(
+        debugger.run_until(&mut sys, 0x208, 100),
+        StopReason::ReachedAddress
+    );
+    assert_eq!(sys.program_counter, 0x208);
+    assert_eq!(sys.v_registers[0], 1);
+}
+
+#[test]
+fn frames_match_run_frame() {
+    let mut expected = system(&COUNTER);
+    expected.time_register = 10;
+    let mut sys = expected.clone();
+    let mut debugger = Debugger::new();
+    // A step before the frame counts towards it
+    assert_eq!(debugger.step(&mut sys), StopReason::Stepped);
+    for _ in 0..3 {
+        expected.run_frame().unwrap();
+        assert_eq!(debugger.run_frame(&mut sys), StopReason::FrameEnd);
+    }
+    assert_eq!(sys.save_state(), expected.save_state());
+    assert_eq!(sys.time_register, 7);
+}
+
+#[test]
+fn run_frame_stops_at_breakpoints_mid_frame() {
+    let mut sys = system(&COUNTER);
+    sys.quirks.display_wait = false;
+    let mut debugger = Debugger::new();
+    let id = debugger.add_breakpoint(Breakpoint::at(0x208));
+    assert_eq!(
+        debugger.run_frame(&mut sys),
+        StopReason::Breakpoint { id, address: 0x208 }
+    );
+    // Resuming runs the instruction at the breakpoint and goes on with the frame
+    assert_eq!(
+        debugger.run_frame(&mut sys),
+        StopReason::Breakpoint { id, address: 0x208 }
+    );
+    assert_eq!(sys.v_registers[0], 2);
+}
+
+#[test]
+fn blocked_systems_do_not_trigger_breakpoints() {
+    // The display wait quirk blocks the rest of the frame after each draw, so the
+    // jump after it only runs at the start of the next frame
+    let mut sys = system(&COUNTER);
+    let mut debugger = Debugger::new();
+    let id = debugger.add_breakpoint(Breakpoint::at(0x208));
+    assert_eq!(debugger.run_frame(&mut sys), StopReason::FrameEnd);
+    assert_eq!(
+        debugger.run_frame(&mut sys),
+        StopReason::Breakpoint { id, address: 0x208 }
+    );
+    assert_eq!(debugger.run_frame(&mut sys), StopReason::FrameEnd);
+    assert_eq!(sys.v_registers[0], 2);
+}
+
+#[test]
+fn steps_report_the_display_wait() {
+    // Default quirks, so the draw blocks the rest of the frame
+    let mut sys = system(&COUNTER);
+    let mut debugger = Debugger::new();
+    assert_eq!(
+        debugger.run_until(&mut sys, 0x206, 100),
+        StopReason::ReachedAddress
+    );
+    assert_eq!(debugger.step(&mut sys), StopReason::Stepped);
+    assert_eq!(sys.program_counter, 0x208);
+    assert_eq!(sys.display().get(1, 0), 1);
+    // The remaining slots of the frame run nothing, then the jump retires
+    let mut waits = 0;
+    while debugger.step(&mut sys) == StopReason::WaitingForVblank {
+        assert_eq!(sys.program_counter, 0x208);
+        waits += 1;
+    }
+    assert_eq!(waits, sys.instructions_per_frame - 4);
+    assert_eq!(sys.program_counter, 0x204);
+}
+
+#[test]
+fn reports_halts_key_waits_and_errors() {
+    let mut debugger = Debugger::new();
+
+    let mut sys = System::with_variant(Variant::SuperChip);
+    sys.load(&[0x00, 0xFD]).unwrap();
+    assert_eq!(debugger.run(&mut sys, 10), StopReason::Halted);
+    assert_eq!(debugger.step(&mut sys), StopReason::Halted);
+
+    let mut sys = system(&[0xF0, 0x0A]);
+    assert_eq!(debugger.run(&mut sys, 10), StopReason::WaitingForKey);
+    // Stepping still runs the frame, so the timers keep going
+    assert_eq!(debugger.step(&mut sys), StopReason::Stepped);
+
+    let mut sys = system(&[0xFF, 0xFF]);
+    assert_eq!(
+        debugger.run(&mut sys, 10),
+        StopReason::Error(EmuError::InvalidOpcode {
+            address: 0x200,
+            opcode: 0xFFFF
+        })
+    );
+    assert_eq!(sys.program_counter, 0x200);
+}
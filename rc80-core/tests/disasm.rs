@@ -0,0 +1,108 @@
+//! Disassembly listings in both syntaxes, compared against expected text.
+
+use rc80_core::disasm::{disassemble, Syntax};
+use rc80_core::{SymbolMap, Variant, PROGRAM_START};
+
+/// A call, a jump, I loads and an `F000 NNNN` long load, which only XO-CHIP decodes as
+/// one four byte instruction. The last bytes don't decode as instructions.
+const ROM: [u8; 19] = [
+    0x22, 0x08, 0x12, 0x02, 0xA2, 0x0C, 0xF0, 0x00, 0x02, 0x0C, 0x00, 0xEE, 0xF0, 0x90, 0xFF, 0x01,
+    0x81, 0x26, 0x3A,
+];
+
+fn listing(variant: Variant, syntax: Syntax) -> String {
+    disassemble(&ROM, PROGRAM_START as u16, variant).render(syntax)
+}
+
+#[test]
+fn chip8_octo() {
+    assert_eq!(
+        listing(Variant::Chip8, Syntax::Octo),
+        "    sub_208                 # 200: 2208
+: label_202
+    jump label_202          # 202: 1202
+    i := data_20c           # 204: a20c
+    0xf0 0x00               # 206: f000
+: sub_208
+    0x02 0x0c               # 208: 020c
+    return                  # 20a: 00ee
+: data_20c
+    0xf0 0x90               # 20c: f090
+    0xff 0x01               # 20e: ff01
+    v1 >>= v2               # 210: 8126
+    0x3a                    # 212: 3a
+"
+    );
+}
+
+#[test]
+fn chip8_cowgod() {
+    assert_eq!(
+        listing(Variant::Chip8, Syntax::Cowgod),
+        "    CALL sub_208            ; 200: 2208
+label_202:
+    JP label_202            ; 202: 1202
+    LD I, data_20c          ; 204: a20c
+    DB 0xf0, 0x00           ; 206: f000
+sub_208:
+    SYS 0x20c               ; 208: 020c
+    RET                     ; 20a: 00ee
+data_20c:
+    DB 0xf0, 0x90           ; 20c: f090
+    DB 0xff, 0x01           ; 20e: ff01
+    SHR V1, V2              ; 210: 8126
+    DB 0x3a                 ; 212: 3a
+"
+    );
+}
+
+#[test]
+fn xochip_octo() {
+    // The long load swallows the call target, and plane 15 has no Octo mnemonic
+    assert_eq!(
+        listing(Variant::XoChip, Syntax::Octo),
+        "    :call 0x208             # 200: 2208
+: label_202
+    jump label_202          # 202: 1202
+    i := data_20c           # 204: a20c
+    i := long data_20c      # 206: f000 020c
+    return                  # 20a: 00ee
+: data_20c
+    0xf0 0x90               # 20c: f090
+    0xff 0x01               # 20e: ff01
+    v1 >>= v2               # 210: 8126
+    0x3a                    # 212: 3a
+"
+    );
+}
+
+#[test]
+fn xochip_cowgod() {
+    assert_eq!(
+        listing(Variant::XoChip, Syntax::Cowgod),
+        "    CALL 0x208              ; 200: 2208
+label_202:
+    JP label_202            ; 202: 1202
+    LD I, data_20c          ; 204: a20c
+    LD I, data_20c          ; 206: f000 020c
+    RET                     ; 20a: 00ee
+data_20c:
+    DB 0xf0, 0x90           ; 20c: f090
+    PLANE 15                ; 20e: ff01
+    SHR V1, V2              ; 210: 8126
+    DB 0x3a                 ; 212: 3a
+"
+    );
+}
+
+#[test]
+fn symbols_replace_generated_labels() {
+    let mut disassembly = disassemble(&ROM, PROGRAM_START as u16, Variant::Chip8);
+    let mut symbols = SymbolMap::new();
+    symbols.insert("draw", 0x208);
+    symbols.insert("start", 0x200);
+    disassembly.apply_symbols(&symbols);
+    let listing = disassembly.render(Syntax::Octo);
+    assert!(listing.starts_with(": start\n    draw                    # 200: 2208\n"));
+    assert!(listing.contains("\n: draw\n    0x02 0x0c"));
+}
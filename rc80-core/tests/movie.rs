@@ -0,0 +1,78 @@
+//! Recording, serializing and replaying input movies.
+
+use rc80_core::{Movie, MovieError, System};
+
+/// Draws random numbers and counts the frames key 0 is held in:
+///
+/// ```text
+/// 0x200  C0FF  V0 = random
+/// 0x202  E19E  skip if key V1 is held
+/// 0x204  1200  jump 0x200
+/// 0x206  7201  V2 += 1
+/// 0x208  1200  jump 0x200
+/// ```
+const ROM: [u8; 10] = [0xC0, 0xFF, 0xE1, 0x9E, 0x12, 0x00, 0x72, 0x01, 0x12, 0x00];
+
+/// Records a few frames, holding key 0 in some of them.
+fn record() -> (Movie, System) {
+    let mut movie = Movie::new(42, &System::default(), &ROM);
+    let mut sys = movie.boot(&ROM).unwrap();
+    for frame in 0..20 {
+        sys.set_pressed_keys(if frame % 3 == 0 { 1 } else { 0 });
+        movie.record_frame(&mut sys).unwrap();
+    }
+    (movie, sys)
+}
+
+#[test]
+fn replays_a_parsed_recording() {
+    let (movie, recorded) = record();
+    assert_eq!(movie.len(), 20);
+    assert_ne!(recorded.v_registers[2], 0);
+
+    let parsed = Movie::parse(&movie.to_text()).unwrap();
+    assert_eq!(parsed, movie);
+    let played = parsed.play(&ROM).unwrap();
+    assert_eq!(played.save_state(), recorded.save_state());
+}
+
+#[test]
+fn detects_desyncs() {
+    let (mut movie, _) = record();
+    // Releasing the key one frame early changes the count from there on
+    movie.frames[9].keys = 0;
+    let expected = movie.frames[9].hash;
+    match movie.play(&ROM) {
+        Err(MovieError::Desync {
+            frame: 9,
+            expected: reported,
+            actual,
+        }) => {
+            assert_eq!(reported, expected);
+            assert_ne!(actual, expected);
+        }
+        other => panic!("expected a desync at frame 9, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn rejects_other_roms_and_frames_past_the_end() {
+    let (movie, _) = record();
+    assert_eq!(
+        movie.boot(&[0x12, 0x00]).err(),
+        Some(MovieError::RomMismatch)
+    );
+
+    let mut sys = movie.boot(&ROM).unwrap();
+    assert_eq!(
+        movie.play_frame(20, &mut sys),
+        Err(MovieError::PastEnd { frame: 20 })
+    );
+}
+
+#[test]
+fn rejects_other_movie_versions() {
+    let (movie, _) = record();
+    let text = movie.to_text().replacen("rc80-movie 2", "rc80-movie 1", 1);
+    assert_eq!(Movie::parse(&text), Err(MovieError::Parse { line: 1 }));
+}
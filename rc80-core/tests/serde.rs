@@ -0,0 +1,83 @@
+//! Serialization of the machine, only built with the `serde` feature:
+//! `cargo test -p rc80-core --features serde`.
+#![cfg(feature = "serde")]
+
+use rc80_core::display::MAX_DISPLAY_WIDTH;
+use rc80_core::font::MAX_FONT_ADDRESS;
+use rc80_core::{Display, System, PLANE_AMOUNT, PROGRAM_START, STACK_SIZE};
+use serde_json::json;
+
+fn checkerboard(width: usize, height: usize, plane_count: usize) -> Display {
+    let pixels: Vec<u8> = (0..width * height)
+        .map(|index| (index * 7 / 3 % (1 << plane_count)) as u8)
+        .collect();
+    Display::from_pixels(width, height, plane_count, &pixels)
+}
+
+#[test]
+fn display_round_trips() {
+    for (width, height, plane_count) in [(64, 32, 1), (128, 64, 2), (MAX_DISPLAY_WIDTH, 3, 8)] {
+        let display = checkerboard(width, height, plane_count);
+        let json = serde_json::to_string(&display).unwrap();
+        assert_eq!(serde_json::from_str::<Display>(&json).unwrap(), display);
+    }
+}
+
+#[test]
+fn oversized_displays_are_rejected() {
+    let wide_row = "#".repeat(MAX_DISPLAY_WIDTH + 72);
+    let json = format!(
+        r#"{{"width":{},"height":1,"planes":[["{}"]]}}"#,
+        MAX_DISPLAY_WIDTH + 72,
+        wide_row
+    );
+    assert!(serde_json::from_str::<Display>(&json).is_err());
+
+    let planes = [r##"["#"]"##; 9].join(",");
+    let json = format!(r#"{{"width":1,"height":1,"planes":[{}]}}"#, planes);
+    assert!(serde_json::from_str::<Display>(&json).is_err());
+
+    let json = r##"{"width":1,"height":2,"planes":[["#"]]}"##;
+    assert!(serde_json::from_str::<Display>(json).is_err());
+}
+
+#[test]
+fn system_round_trips() {
+    let mut sys = System::with_seed(42);
+    sys.load(&[0xC0, 0xFF, 0xA2, 0x00, 0xD0, 0x05, 0x12, 0x00])
+        .unwrap();
+    for _ in 0..10 {
+        sys.run_frame().unwrap();
+    }
+    let json = serde_json::to_string(&sys).unwrap();
+    let mut copy: System = serde_json::from_str(&json).unwrap();
+    assert_eq!(copy.save_state(), sys.save_state());
+    sys.run_frame().unwrap();
+    copy.run_frame().unwrap();
+    assert_eq!(copy.save_state(), sys.save_state());
+}
+
+#[test]
+fn invalid_systems_are_rejected() {
+    let valid = serde_json::to_value(System::default()).unwrap();
+    assert!(serde_json::from_value::<System>(valid.clone()).is_ok());
+    let small_display = serde_json::to_value(Display::new(8, 8, PLANE_AMOUNT)).unwrap();
+    for (field, value) in [
+        ("stack_pointer", json!(STACK_SIZE + 1)),
+        ("memory", json!(vec![0; PROGRAM_START - 1])),
+        ("key_wait", json!({ "register": 16, "pressed": null })),
+        ("key_wait", json!({ "register": 0, "pressed": 16 })),
+        ("planes", json!(1 << PLANE_AMOUNT)),
+        ("font_address", json!(MAX_FONT_ADDRESS + 1)),
+        ("display", small_display),
+    ] {
+        let mut bad = valid.clone();
+        bad[field] = value.clone();
+        assert!(
+            serde_json::from_value::<System>(bad).is_err(),
+            "accepted {} = {}",
+            field,
+            value
+        );
+    }
+}
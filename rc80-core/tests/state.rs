@@ -0,0 +1,146 @@
+//! Save states: round trips, rejection of damaged states, and loading of states written
+//! by older versions of the format.
+
+use rc80_core::{StateError, System, Variant};
+
+/// Draws a sprite, sets some registers and the timers, then calls a subroutine that
+/// spins forever:
+///
+/// ```text
+/// : main
+///     v0 := 5 v1 := 3
+///     i := sprite
+///     sprite v0 v1 3
+///     va := 0x2a
+///     v2 := 60 delay := v2 buzzer := v2
+///     v3 := random 0xff
+///     spin
+/// : spin
+///     jump spin
+/// : sprite
+///     0xF0 0x90 0xF0
+/// ```
+const ROM: [u8; 25] = [
+    0x60, 0x05, 0x61, 0x03, 0xa2, 0x16, 0xd0, 0x13, 0x6a, 0x2a, 0x62, 0x3c, 0xf2, 0x15, 0xf2, 0x18,
+    0xc3, 0xff, 0x22, 0x14, 0x12, 0x14, 0xf0, 0x90, 0xf0,
+];
+
+/// `ROM` after 10 frames with seed 1, saved with version 1 of the format.
+const STATE_V1: &[u8] = include_bytes!("fixtures/state-v1.bin");
+
+fn running(variant: Variant) -> System {
+    let mut sys = System::with_seed(1);
+    sys.set_variant(variant);
+    sys.load(&ROM).unwrap();
+    for _ in 0..10 {
+        sys.run_frame().unwrap();
+    }
+    sys
+}
+
+#[test]
+fn round_trips() {
+    for variant in [Variant::Chip8, Variant::SuperChip, Variant::XoChip] {
+        let mut sys = running(variant);
+        sys.press_key(0x7);
+        let state = sys.save_state();
+
+        let mut copy = System::default();
+        copy.load_state(&state).unwrap();
+        assert_eq!(copy.save_state(), state);
+        // Including the random number generator, so both go on the same way
+        sys.program_counter = 0x210;
+        copy.program_counter = 0x210;
+        sys.step().unwrap();
+        copy.step().unwrap();
+        assert_eq!(copy.save_state(), sys.save_state());
+    }
+}
+
+#[test]
+fn loads_version_1() {
+    let mut sys = System::default();
+    sys.load_state(STATE_V1).unwrap();
+    assert_eq!(sys.variant, Variant::Chip8);
+    assert_eq!(sys.program_counter, 0x214);
+    assert_eq!(sys.stack_pointer, 1);
+    assert_eq!(sys.stack[0], 0x212);
+    assert_eq!(sys.i_register, 0x216);
+    assert_eq!(sys.v_registers[..4], [5, 3, 60, 71]);
+    assert_eq!(sys.v_registers[0xA], 0x2A);
+    assert_eq!((sys.time_register, sys.sound_register), (51, 51));
+    assert_eq!(sys.memory[0x200..0x200 + ROM.len()], ROM);
+    let lit: Vec<_> = sys.display().lit_pixels().map(|(x, y, _)| (x, y)).collect();
+    assert_eq!(
+        lit,
+        [
+            (5, 3),
+            (6, 3),
+            (7, 3),
+            (8, 3),
+            (5, 4),
+            (8, 4),
+            (5, 5),
+            (6, 5),
+            (7, 5),
+            (8, 5)
+        ]
+    );
+    // The saved generator state comes back too
+    sys.program_counter = 0x210;
+    sys.step().unwrap();
+    let mut expected = running(Variant::Chip8);
+    expected.program_counter = 0x210;
+    expected.step().unwrap();
+    assert_eq!(sys.v_registers[3], expected.v_registers[3]);
+}
+
+/// Loads `state` into a running system and checks it fails with `expected` without
+/// changing anything.
+fn assert_rejected(state: &[u8], expected: StateError) {
+    let mut sys = running(Variant::SuperChip);
+    let before = sys.save_state();
+    assert_eq!(sys.load_state(state), Err(expected));
+    assert_eq!(sys.save_state(), before);
+}
+
+#[test]
+fn rejects_truncated_states() {
+    let state = running(Variant::Chip8).save_state();
+    for len in (6..state.len()).step_by(7).chain([state.len() - 1]) {
+        assert_rejected(&state[..len], StateError::Truncated);
+    }
+    assert_rejected(&state[..3], StateError::NotAState);
+}
+
+#[test]
+fn rejects_corrupted_states() {
+    let state = running(Variant::Chip8).save_state();
+    let corrupt = |offset: usize, value: u8| {
+        let mut state = state.clone();
+        state[offset] = value;
+        state
+    };
+    assert_rejected(&corrupt(0, b'X'), StateError::NotAState);
+    assert_rejected(
+        &corrupt(4, 0),
+        StateError::UnsupportedVersion { version: 0 },
+    );
+    assert_rejected(
+        &corrupt(4, 2),
+        StateError::UnsupportedVersion { version: 2 },
+    );
+    assert_rejected(&corrupt(6, 7), StateError::Invalid { field: "variant" });
+    // Variant, quirks, trigger, instructions per frame, font, font address, then the
+    // program counter and the stack pointer
+    let stack_pointer = 6 + 1 + 4 + 1 + 4 + 1 + 2 + 2;
+    assert_rejected(
+        &corrupt(stack_pointer, 17),
+        StateError::Invalid {
+            field: "stack pointer",
+        },
+    );
+    let mut long = state.clone();
+    long.push(0);
+    assert_rejected(&long, StateError::Invalid { field: "length" });
+}
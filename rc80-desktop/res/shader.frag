@@ -1,6 +1,8 @@
 #version 330
 
+in vec3 v_color;
+
 void main()
 {
-    gl_FragColor = vec4(1.0);
+    gl_FragColor = vec4(v_color, 1.0);
 }
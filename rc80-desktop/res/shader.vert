@@ -1,10 +1,14 @@
 #version 330
 
 in vec2 i_pos;
+in vec3 i_color;
+
+out vec3 v_color;
 
 uniform mat4 u_projection;
 
 void main()
 {
     gl_Position = u_projection * vec4(i_pos, 0.0, 1.0);
+    v_color = i_color;
 }
@@ -3,31 +3,292 @@ use eframe::{
     egui_glow, glow,
 };
 use std::mem::size_of;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-use rc80_core::System;
+use rc80_core::{
+    Breakpoint, Debugger, Display, EmuError, Movie, MovieError, Quirks, Rewind, StopReason, System,
+    Variant, TIMER_FREQUENCY,
+};
+
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / TIMER_FREQUENCY as u64);
+/// Upper bound on frames emulated per repaint, so a stalled window doesn't fast-forward.
+const MAX_FRAMES_PER_UPDATE: u32 = 4;
+/// Pixel colors indexed by the planes a pixel is lit on. Single-plane programs only
+/// use the first two.
+const PALETTE: [[f32; 3]; 4] = [[0., 0., 0.], [1., 1., 1.], [1., 0.4, 0.], [0.4, 0.13, 0.]];
+
+/// Host key that rewinds while held.
+const REWIND_KEY: egui::Key = egui::Key::Backspace;
+
+/// Host keys for the hex keypad, indexed by CHIP-8 key value.
+///
+/// ```text
+/// 1 2 3 C      1 2 3 4
+/// 4 5 6 D  ->  Q W E R
+/// 7 8 9 E      A S D F
+/// A 0 B F      Z X C V
+/// ```
+const KEYPAD_MAP: [egui::Key; 16] = [
+    egui::Key::X,
+    egui::Key::Num1,
+    egui::Key::Num2,
+    egui::Key::Num3,
+    egui::Key::Q,
+    egui::Key::W,
+    egui::Key::E,
+    egui::Key::A,
+    egui::Key::S,
+    egui::Key::D,
+    egui::Key::Z,
+    egui::Key::C,
+    egui::Key::Num4,
+    egui::Key::R,
+    egui::Key::F,
+    egui::Key::V,
+];
+
+/// What the input movie controls are doing.
+enum MovieMode {
+    Idle,
+    Recording(Movie),
+    /// Playing back a movie, along with the next frame to play.
+    Playing(Movie, usize),
+}
 
 struct EmuApp {
     render: Arc<Mutex<EmuRender>>,
     sys: System,
     play_sim: bool,
+    error: Option<EmuError>,
+    /// Outcome of the last save state action, shown under the controls.
+    state_message: Option<String>,
+    state_path: PathBuf,
+    rewind: Rewind,
+    /// Whether the rewind button or hotkey is held down.
+    rewinding: bool,
+    rom: Vec<u8>,
+    movie: MovieMode,
+    /// Outcome of the last movie action, shown under the controls.
+    movie_message: Option<String>,
+    movie_path: PathBuf,
+    debugger: Debugger,
+    /// Text of the breakpoint being typed in.
+    breakpoint_text: String,
+    /// Why the debugger last paused, or why a breakpoint didn't parse.
+    debug_message: Option<String>,
+    last_update: Instant,
+    frame_time: Duration,
 }
 
 impl EmuApp {
     fn new(cc: &eframe::CreationContext<'_>) -> Self {
-        let mut sys = System::default();
-        let bytes = include_bytes!("/home/mew/Downloads/3-corax+.ch8");
-        sys.load(bytes);
+        let mut sys = System::with_seed(time_seed());
+        let rom_path = PathBuf::from(
+            std::env::args()
+                .nth(1)
+                .expect("usage: rc80-desktop <rom.ch8>"),
+        );
+        // Pick the variant from the conventional extensions, plain CHIP-8 otherwise
+        match rom_path
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("sc8") => sys.set_variant(Variant::SuperChip),
+            Some("xo8") => sys.set_variant(Variant::XoChip),
+            _ => {}
+        }
+        let bytes = std::fs::read(&rom_path).expect("cannot read rom file");
+        let error = sys.load(&bytes).err();
         let gl = cc.gl.as_ref().expect("glow backend is not enabled");
-        sys.pixels[0] = 1;
-        sys.pixels[1] = 1;
-        sys.pixels[727] = 1;
-        sys.pixels[1116] = 1;
-        println!("{:b}", sys.pixels[0]);
         Self {
             render: Arc::new(Mutex::new(EmuRender::new(gl))),
             sys,
             play_sim: false,
+            error,
+            state_message: None,
+            state_path: rom_path.with_extension("state"),
+            rewind: Rewind::default(),
+            rewinding: false,
+            rom: bytes,
+            movie: MovieMode::Idle,
+            movie_message: None,
+            movie_path: rom_path.with_extension("movie"),
+            debugger: Debugger::new(),
+            breakpoint_text: String::new(),
+            debug_message: None,
+            last_update: Instant::now(),
+            frame_time: Duration::ZERO,
+        }
+    }
+
+    fn save_state(&mut self) {
+        let message = match std::fs::write(&self.state_path, self.sys.save_state()) {
+            Ok(()) => format!("Saved state to {}", self.state_path.display()),
+            Err(err) => format!("Cannot save state: {}", err),
+        };
+        self.state_message = Some(message);
+    }
+
+    fn load_state(&mut self) {
+        let result = std::fs::read(&self.state_path)
+            .map_err(|err| err.to_string())
+            .and_then(|bytes| self.sys.load_state(&bytes).map_err(|err| err.to_string()));
+        let message = match result {
+            Ok(()) => {
+                self.error = None;
+                format!("Loaded state from {}", self.state_path.display())
+            }
+            Err(err) => format!("Cannot load state: {}", err),
+        };
+        self.state_message = Some(message);
+    }
+
+    /// Restarts the ROM on a fresh system and records the keypad into a movie.
+    fn start_recording(&mut self) {
+        let movie = Movie::new(time_seed(), &self.sys, &self.rom);
+        match movie.boot(&self.rom) {
+            Ok(sys) => {
+                self.restart(sys);
+                self.movie = MovieMode::Recording(movie);
+                self.movie_message = Some("Recording".to_owned());
+            }
+            Err(err) => self.movie_message = Some(format!("Cannot record movie: {}", err)),
+        }
+    }
+
+    /// Restarts the ROM and plays back the movie saved next to it.
+    fn start_playback(&mut self) {
+        let result = std::fs::read_to_string(&self.movie_path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| Movie::parse(&text).map_err(|err| err.to_string()))
+            .and_then(|movie| match movie.boot(&self.rom) {
+                Ok(sys) => Ok((movie, sys)),
+                Err(err) => Err(err.to_string()),
+            });
+        match result {
+            Ok((movie, sys)) => {
+                self.restart(sys);
+                self.movie_message = Some(format!("Playing {} frames", movie.len()));
+                self.movie = MovieMode::Playing(movie, 0);
+            }
+            Err(err) => self.movie_message = Some(format!("Cannot play movie: {}", err)),
+        }
+    }
+
+    /// Ends the recording or playback, saving a recorded movie next to the ROM.
+    fn stop_movie(&mut self) {
+        let message = match std::mem::replace(&mut self.movie, MovieMode::Idle) {
+            MovieMode::Idle => return,
+            MovieMode::Recording(movie) => {
+                match std::fs::write(&self.movie_path, movie.to_text()) {
+                    Ok(()) => format!(
+                        "Saved {} frames to {}",
+                        movie.len(),
+                        self.movie_path.display()
+                    ),
+                    Err(err) => format!("Cannot save movie: {}", err),
+                }
+            }
+            MovieMode::Playing(movie, frame) => {
+                format!("Stopped playback at frame {} of {}", frame, movie.len())
+            }
+        };
+        self.movie_message = Some(message);
+    }
+
+    /// Swaps in a freshly booted system and starts running it.
+    fn restart(&mut self, sys: System) {
+        self.sys = sys;
+        self.error = None;
+        self.rewind.clear();
+        self.play_sim = true;
+        self.last_update = Instant::now();
+        self.frame_time = Duration::ZERO;
+    }
+
+    fn update_keypad(&mut self, ctx: &egui::Context) {
+        // The movie drives the keypad during playback
+        if let MovieMode::Playing(..) = self.movie {
+            return;
+        }
+        let keys = KEYPAD_MAP
+            .iter()
+            .enumerate()
+            .filter(|(_, host_key)| ctx.input(|i| i.key_down(**host_key)))
+            .fold(0, |keys, (key, _)| keys | 1 << key);
+        self.sys.set_pressed_keys(keys);
+    }
+
+    /// Runs one frame, recording or playing it back if a movie is active. Playback
+    /// problems stop the movie and pause rather than count as emulator errors.
+    /// Breakpoints only apply outside of movies, and pause midway through the frame.
+    fn run_frame(&mut self) -> Result<(), EmuError> {
+        let (movie, frame) = match &mut self.movie {
+            MovieMode::Idle => {
+                match self.debugger.run_frame(&mut self.sys) {
+                    StopReason::FrameEnd => {}
+                    StopReason::Error(err) => return Err(err),
+                    stop => {
+                        self.debug_message = Some(format!("Stopped at {}", stop));
+                        self.play_sim = false;
+                    }
+                }
+                return Ok(());
+            }
+            MovieMode::Recording(movie) => return movie.record_frame(&mut self.sys),
+            MovieMode::Playing(movie, frame) => (movie, frame),
+        };
+        let message = if *frame == movie.len() {
+            "Playback finished".to_owned()
+        } else {
+            match movie.play_frame(*frame, &mut self.sys) {
+                Ok(()) => {
+                    *frame += 1;
+                    return Ok(());
+                }
+                Err(MovieError::Emu { error, .. }) => return Err(error),
+                Err(err) => err.to_string(),
+            }
+        };
+        self.movie = MovieMode::Idle;
+        self.movie_message = Some(message);
+        self.play_sim = false;
+        Ok(())
+    }
+
+    fn run_frames(&mut self) {
+        let now = Instant::now();
+        self.frame_time += now - self.last_update;
+        self.last_update = now;
+        let mut frames = 0;
+        while self.frame_time >= FRAME_DURATION {
+            self.frame_time -= FRAME_DURATION;
+            if frames < MAX_FRAMES_PER_UPDATE {
+                if self.rewinding && matches!(self.movie, MovieMode::Idle) {
+                    // Steps back one snapshot per frame, which rewinds faster than real time
+                    match self.rewind.rewind(&mut self.sys) {
+                        Ok(true) => self.error = None,
+                        Ok(false) => {}
+                        Err(err) => {
+                            self.state_message = Some(format!("Cannot rewind: {}", err));
+                        }
+                    }
+                } else {
+                    if let Err(err) = self.run_frame() {
+                        self.error = Some(err);
+                        self.play_sim = false;
+                        self.stop_movie();
+                        return;
+                    }
+                    if !self.play_sim {
+                        return;
+                    }
+                    self.rewind.record(&self.sys);
+                }
+                frames += 1;
+            }
         }
     }
 
@@ -38,12 +299,12 @@ impl EmuApp {
         );
 
         let render = self.render.clone();
-        let pixels = self.sys.pixels;
+        let display = self.sys.display().clone();
 
         let callback = egui::PaintCallback {
             rect,
             callback: Arc::new(egui_glow::CallbackFn::new(move |_info, painter| {
-                render.lock().update_buffers(pixels, painter.gl());
+                render.lock().update_buffers(&display, painter.gl());
                 render.lock().paint(painter.gl());
             })),
         };
@@ -53,24 +314,178 @@ impl EmuApp {
 
 impl eframe::App for EmuApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        self.update_keypad(ctx);
+        // Anything that changes the machine behind the movie's back would desync it
+        let movie_idle = matches!(self.movie, MovieMode::Idle);
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("Hello eframe!");
             ui.label(format!(
                 "Status: {}",
-                if self.play_sim { "Playing" } else { "Paused" }
+                if self.rewinding && movie_idle {
+                    "Rewinding"
+                } else if !self.play_sim {
+                    "Paused"
+                } else if self.sys.is_waiting_for_key() {
+                    "Waiting for key"
+                } else {
+                    "Playing"
+                }
             ));
+            if let Some(err) = &self.error {
+                ui.colored_label(egui::Color32::RED, format!("Error: {}", err));
+            }
+            if let Some(message) = &self.debug_message {
+                ui.label(message);
+            }
             ui.horizontal(|ui| {
+                if ui.button("Save state").clicked() {
+                    self.save_state();
+                }
                 if ui
-                    .button(if self.play_sim { "Pause" } else { "Play" })
+                    .add_enabled(movie_idle, egui::Button::new("Load state"))
+                    .clicked()
+                {
+                    self.load_state();
+                }
+                if let Some(message) = &self.state_message {
+                    ui.label(message);
+                }
+            });
+            ui.horizontal(|ui| {
+                if movie_idle {
+                    if ui.button("Record movie").clicked() {
+                        self.start_recording();
+                    }
+                    if ui.button("Play movie").clicked() {
+                        self.start_playback();
+                    }
+                } else if ui.button("Stop movie").clicked() {
+                    self.stop_movie();
+                }
+                if let Some(message) = &self.movie_message {
+                    ui.label(message);
+                }
+            });
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(
+                        self.error.is_none(),
+                        egui::Button::new(if self.play_sim { "Pause" } else { "Play" }),
+                    )
                     .clicked()
                 {
                     self.play_sim = !self.play_sim;
+                    self.debug_message = None;
+                    self.last_update = Instant::now();
+                    self.frame_time = Duration::ZERO;
                 }
                 if ui
-                    .add_enabled(!self.play_sim, egui::Button::new("Step"))
+                    .add_enabled(
+                        !self.play_sim && self.error.is_none() && movie_idle,
+                        egui::Button::new("Step"),
+                    )
                     .clicked()
                 {
-                    self.sys.step();
+                    match self.debugger.step(&mut self.sys) {
+                        StopReason::Error(err) => self.error = Some(err),
+                        StopReason::WaitingForVblank => {
+                            self.debug_message = Some("Waiting for the end of the frame".to_owned())
+                        }
+                        _ => self.debug_message = None,
+                    }
+                }
+                let rewind_button = ui
+                    .add_enabled(
+                        !self.rewind.is_empty() && movie_idle,
+                        egui::Button::new("Rewind"),
+                    )
+                    .on_hover_text("Hold to rewind, or hold Backspace");
+                // Otherwise holding Backspace while paused would run the movie's frames
+                let rewinding = movie_idle
+                    && (rewind_button.is_pointer_button_down_on()
+                        || ctx.input(|i| i.key_down(REWIND_KEY)));
+                if rewinding && !self.rewinding {
+                    self.last_update = Instant::now();
+                    self.frame_time = Duration::ZERO;
+                }
+                self.rewinding = rewinding;
+                ui.label("Instructions per frame:");
+                ui.add_enabled(
+                    movie_idle,
+                    egui::DragValue::new(&mut self.sys.instructions_per_frame)
+                        .clamp_range(1..=1000),
+                );
+            });
+            ui.horizontal(|ui| {
+                ui.label("Variant:");
+                for (variant, name) in [
+                    (Variant::Chip8, "CHIP-8"),
+                    (Variant::SuperChip, "SUPER-CHIP"),
+                    (Variant::XoChip, "XO-CHIP"),
+                ] {
+                    if ui
+                        .add_enabled(
+                            movie_idle,
+                            egui::RadioButton::new(self.sys.variant == variant, name),
+                        )
+                        .clicked()
+                    {
+                        self.sys.set_variant(variant);
+                    }
+                }
+            });
+            ui.add_enabled_ui(movie_idle, |ui| {
+                ui.collapsing("Quirks", |ui| {
+                    let quirks = &mut self.sys.quirks;
+                    ui.horizontal(|ui| {
+                        if ui.button("COSMAC VIP").clicked() {
+                            *quirks = Quirks::cosmac_vip();
+                        }
+                        if ui.button("SUPER-CHIP").clicked() {
+                            *quirks = Quirks::super_chip();
+                        }
+                        if ui.button("XO-CHIP").clicked() {
+                            *quirks = Quirks::xo_chip();
+                        }
+                    });
+                    ui.checkbox(&mut quirks.shift_uses_vy, "Shifts use VY");
+                    ui.checkbox(&mut quirks.memory_increments_i, "FX55/FX65 increment I");
+                    ui.checkbox(&mut quirks.jump_uses_vx, "BNNN jumps with VX");
+                    ui.checkbox(&mut quirks.logic_resets_vf, "Logic ops reset VF");
+                    ui.checkbox(&mut quirks.display_wait, "Display wait");
+                    ui.checkbox(&mut quirks.clip_sprites, "Clip sprites");
+                    ui.checkbox(&mut quirks.collision_counts_rows, "Count collided rows");
+                })
+            });
+            ui.collapsing("Breakpoints", |ui| {
+                ui.horizontal(|ui| {
+                    let input = ui
+                        .text_edit_singleline(&mut self.breakpoint_text)
+                        .on_hover_text("0x2a4, DXYN or if V3 == 0x10 && [I] != 0");
+                    let submitted =
+                        input.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                    if ui.button("Add").clicked() || submitted {
+                        match Breakpoint::parse(&self.breakpoint_text) {
+                            Ok(breakpoint) => {
+                                self.debugger.add_breakpoint(breakpoint);
+                                self.breakpoint_text.clear();
+                            }
+                            Err(err) => {
+                                self.debug_message = Some(format!("Bad breakpoint: {}", err))
+                            }
+                        }
+                    }
+                });
+                let ids: Vec<_> = self.debugger.breakpoints().map(|(id, _)| id).collect();
+                for id in ids {
+                    ui.horizontal(|ui| {
+                        let breakpoint = self.debugger.breakpoint_mut(id).unwrap();
+                        let label = format!("{} {}", id, breakpoint);
+                        ui.checkbox(&mut breakpoint.enabled, label);
+                        if ui.button("Remove").clicked() {
+                            self.debugger.remove_breakpoint(id);
+                        }
+                    });
                 }
             });
             egui::Frame::canvas(ui.style()).show(ui, |ui| {
@@ -78,8 +493,8 @@ impl eframe::App for EmuApp {
             });
         });
 
-        if self.play_sim {
-            self.sys.step();
+        if self.play_sim || self.rewinding {
+            self.run_frames();
             ctx.request_repaint();
         }
     }
@@ -118,6 +533,8 @@ impl EmuRender {
                     shader
                 })
                 .collect();
+            gl.bind_attrib_location(program, 0, "i_pos");
+            gl.bind_attrib_location(program, 1, "i_color");
             gl.link_program(program);
             gl.use_program(Some(program));
             assert!(
@@ -139,25 +556,28 @@ impl EmuRender {
             let vertex_buffer = gl.create_buffer().expect("cannot create buffer");
             gl.bind_buffer(glow::ARRAY_BUFFER, Some(vertex_buffer));
             gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, &[0], glow::DYNAMIC_DRAW);
+            let stride = (5 * size_of::<f32>()) as i32;
             gl.enable_vertex_attrib_array(0);
+            gl.vertex_attrib_pointer_f32(0, 2, glow::FLOAT, false, stride, 0);
+            gl.enable_vertex_attrib_array(1);
             gl.vertex_attrib_pointer_f32(
-                0,
-                2,
+                1,
+                3,
                 glow::FLOAT,
                 false,
+                stride,
                 (2 * size_of::<f32>()) as i32,
-                0,
             );
 
             let index_buffer = gl.create_buffer().expect("cannot create buffer");
             gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(index_buffer));
             gl.buffer_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, &[0], glow::STREAM_DRAW);
 
-            use rc80_core::{SCREEN_HEIGHT, SCREEN_WIDTH};
+            // Vertices are in screen-relative units, (0, 0) to (1, 1)
             #[rustfmt::skip]
             let proj = [
-                2. / SCREEN_WIDTH as f32, 0., 0., -1.,
-                0., -2. / SCREEN_HEIGHT as f32, 0., 1.,
+                2., 0., 0., -1.,
+                0., -2., 0., 1.,
                 0., 0., -1., 0.,
                 0., 0., 0., 1.,
             ];
@@ -176,22 +596,23 @@ impl EmuRender {
         }
     }
 
-    fn update_buffers(&mut self, pixels: rc80_core::ScreenPixels, gl: &glow::Context) {
+    fn update_buffers(&mut self, display: &Display, gl: &glow::Context) {
         use glow::HasContext as _;
         unsafe {
             let mut vertices = Vec::<f32>::new();
             let mut indices = Vec::<u32>::new();
             let mut next_index = 0;
-            for (index, pixel) in pixels.into_iter().enumerate() {
-                use rc80_core::SCREEN_WIDTH;
-                if pixel == 1 {
-                    let x = (index % SCREEN_WIDTH) as f32;
-                    let y = (index / SCREEN_WIDTH) as f32;
-                    vertices.extend_from_slice(&[x, y, x + 1., y, x + 1., y + 1., x, y + 1.]);
-                    let i = next_index;
-                    indices.extend_from_slice(&[i, i + 1, i + 2, i + 2, i + 3, i]);
-                    next_index += 4;
+            let w = 1. / display.width() as f32;
+            let h = 1. / display.height() as f32;
+            for (x, y, planes) in display.lit_pixels() {
+                let (x, y) = (x as f32 * w, y as f32 * h);
+                let [r, g, b] = PALETTE[planes as usize & 3];
+                for (x, y) in [(x, y), (x + w, y), (x + w, y + h), (x, y + h)] {
+                    vertices.extend_from_slice(&[x, y, r, g, b]);
                 }
+                let i = next_index;
+                indices.extend_from_slice(&[i, i + 1, i + 2, i + 2, i + 3, i]);
+                next_index += 4;
             }
             let vertices_u8: &[u8] = core::slice::from_raw_parts(
                 vertices.as_ptr() as *const u8,
@@ -239,3 +660,10 @@ fn main() {
     )
     .unwrap()
 }
+
+/// A seed that differs from run to run, for the random number generator.
+fn time_seed() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64)
+}
@@ -0,0 +1,105 @@
+use std::process::ExitCode;
+
+use rc80_core::disasm::{self, Syntax};
+use rc80_core::{SymbolMap, Variant, PROGRAM_START};
+
+const USAGE: &str = "usage: rc80-disasm [--syntax octo|cowgod] [--variant chip8|superchip|xochip] \
+[--origin ADDR] [--symbols FILE] <rom.ch8>";
+
+fn parse_address(text: &str) -> Option<u16> {
+    match text.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => text.parse().ok(),
+    }
+}
+
+fn main() -> ExitCode {
+    let mut syntax = Syntax::Octo;
+    let mut variant = None;
+    let mut origin = PROGRAM_START as u16;
+    let mut symbols_path = None;
+    let mut path = None;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--syntax" => match args.next().as_deref() {
+                Some("octo") => syntax = Syntax::Octo,
+                Some("cowgod") => syntax = Syntax::Cowgod,
+                _ => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--variant" => match args.next().as_deref() {
+                Some("chip8") => variant = Some(Variant::Chip8),
+                Some("superchip") => variant = Some(Variant::SuperChip),
+                Some("xochip") => variant = Some(Variant::XoChip),
+                _ => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--origin" => match args.next().as_deref().and_then(parse_address) {
+                Some(address) => origin = address,
+                None => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::FAILURE;
+                }
+            },
+            "--symbols" => match args.next() {
+                Some(file) => symbols_path = Some(file),
+                None => {
+                    eprintln!("{}", USAGE);
+                    return ExitCode::FAILURE;
+                }
+            },
+            _ if path.is_none() => path = Some(arg),
+            _ => {
+                eprintln!("{}", USAGE);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+
+    let bytes = match std::fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("cannot read {}: {}", path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let variant = variant.unwrap_or_else(|| {
+        // Same conventional extensions as the desktop app
+        if path.ends_with(".sc8") {
+            Variant::SuperChip
+        } else if path.ends_with(".xo8") {
+            Variant::XoChip
+        } else {
+            Variant::Chip8
+        }
+    });
+    let mut disassembly = disasm::disassemble(&bytes, origin, variant);
+    if let Some(symbols_path) = symbols_path {
+        let symbols = match std::fs::read_to_string(&symbols_path) {
+            Ok(text) => SymbolMap::parse(&text),
+            Err(err) => {
+                eprintln!("cannot read {}: {}", symbols_path, err);
+                return ExitCode::FAILURE;
+            }
+        };
+        match symbols {
+            Ok(symbols) => disassembly.apply_symbols(&symbols),
+            Err(line) => {
+                eprintln!("{}:{}: invalid symbol", symbols_path, line);
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    print!("{}", disassembly.render(syntax));
+    ExitCode::SUCCESS
+}